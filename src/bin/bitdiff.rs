@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, Context};
+
+use clap::{Parser};
+use samsunghvac2mqtt::{bitdiff::{BitDiff, Event}, capture::Capture};
+
+
+/// Report which bits change across a capture from the `dump` tool
+///
+/// Frames are grouped by (source class, destination class, command). Each bit change is
+/// correlated with `0xa0` settings changes and annotated events shortly before it.
+/// The capture's notes are events too, at the time of the frame following them.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Capture to analyse (`dump` text output)
+    capture: PathBuf,
+
+    /// An event at a time in the capture, e.g. `--event "125000=pressed swing"`
+    #[arg(long = "event", value_parser = parse_event)]
+    events: Vec<Event>,
+
+    /// How long before a change an `0xa0` or event is considered related (ms)
+    #[arg(long, default_value_t = 2000)]
+    window_ms: u64,
+
+    /// Leave bits that change more often than this out of the timeline (e.g., temperatures)
+    #[arg(long, default_value_t = 20)]
+    max_changes: usize,
+}
+
+fn parse_event(s: &str) -> Result<Event> {
+    let (elapsed_ms, label) = s.split_once('=')
+        .context("expected ELAPSED_MS=LABEL")?;
+
+    Ok(Event {
+        elapsed_ms: elapsed_ms.trim().parse().context("invalid event time")?,
+        label: label.trim().to_string()
+    })
+}
+
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let capture = std::fs::read_to_string(&args.capture)
+        .with_context(|| format!("failed to read {}", args.capture.display()))?;
+
+    let capture = Capture::parse(&capture)
+        .with_context(|| format!("failed to parse {}", args.capture.display()))?;
+
+    let mut events = Event::from_notes(&capture);
+    events.extend(args.events);
+
+    let mut diff = BitDiff::new(events);
+
+    for frame in &capture.frames {
+        diff.add(frame);
+    }
+
+    print!("{}", diff.report(args.window_ms, args.max_changes));
+
+    Ok(())
+}
@@ -1,123 +1,99 @@
-use std::{sync::Arc, pin::Pin, collections::HashMap};
+use std::time::Duration;
 
-use anyhow::{Result, bail, Context};
+use anyhow::Result;
 
 use clap::{Parser};
-use samsunghvac2mqtt::config::Port;
-use tokio::{net::{TcpListener, TcpStream}, sync::Mutex, io::{AsyncWriteExt, AsyncReadExt, AsyncWrite, AsyncRead, split, ReadHalf, WriteHalf}};
-use tokio_serial::SerialPortBuilderExt;
+use samsunghvac2mqtt::{bridge::Bridge, config::Port, protocol::codec::WrcBusProtocolCodec};
+use tokio::net::TcpListener;
+use tokio_util::codec::Framed;
 use url::Url;
 
 
-/// A helper tool 
+/// Wait before accepting again after an error, as e.g. running out of file descriptors doesn't clear immediately.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// A helper tool that connects several ports (e.g. the physical bus and emulators) into one bus
+///
+/// Only whole, valid frames are forwarded between the ports.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Address and port to listen on (host:port) for bus clients
     listen: String,
 
+    /// Path of a unix socket to additionally listen on for bus clients
+    #[cfg(unix)]
+    #[arg(long)]
+    listen_unix: Option<String>,
+
     /// URLs of additional ports to attach to the bus.
-    /// 
-    /// either serial:///device/path or tcp+raw://host:port URLs supported 
+    ///
+    /// any port URL is supported, e.g. serial:///device/path, tcp+raw://host:port or replay://dumps/capture.txt
     #[arg(long)]
     attach: Vec<Url>
 }
 
 
-
-struct SharedState {
-    next_port_id: usize,
-    writers: HashMap<usize, Pin<Box<dyn AsyncWrite + Send>>>
-}
-
-impl SharedState {
-    fn new() -> Self {
-        SharedState { next_port_id: 0, writers: Default::default() }
-    }
-}
-
-
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
     let listener = TcpListener::bind(&args.listen).await.unwrap();
 
-    let state = Arc::new(Mutex::new(SharedState::new()));
+    let bridge = Bridge::default();
 
     for url in args.attach {
-        let id = match Port::open(&url).await? {
-            Port::Serial(port) => {
-                attach_port(port, state.clone()).await
-            },
-            Port::TcpRaw(stream) => {
-                attach_port(stream, state.clone()).await
-            }
-        };
+        let id = bridge.attach(Port::open(&url).await?.framed()).await;
 
         println!("{url} attached as client {id}");
     }
 
-    println!("listening on {}", args.listen);
-
-    loop {
-        let (socket, addr) = listener.accept().await.unwrap();
-
-        socket.set_nodelay(true)?;
-
-        let id = attach_port(socket, state.clone()).await;
-
-        println!("new connection from {addr} attached as client {id}");
-    }
-}
-
-async fn attach_port<T>(socket: T, state: Arc<Mutex<SharedState>>) -> usize where
-    T: AsyncRead + AsyncWrite + Unpin + Send + 'static
-{
-    let (rx, tx) = split(socket);
+    #[cfg(unix)]
+    if let Some(path) = args.listen_unix {
+        let listener = samsunghvac2mqtt::config::bind_unix_listener(&path)?;
 
-    let id = {
-        let mut state = state.lock().await;
+        println!("listening on {path}");
 
-        let id = state.next_port_id;
-        state.next_port_id += 1;
-        
-        state.writers.insert(id, Box::pin(tx));
+        tokio::spawn({
+            let bridge = bridge.clone();
 
-        id
-    };
+            async move {
+                loop {
+                    let socket = match listener.accept().await {
+                        Ok((socket, _)) => socket,
+                        Err(err) => {
+                            println!("failed to accept connection on {path}: {err}");
+                            tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                            continue;
+                        }
+                    };
 
-    tokio::spawn(async move {
-        process(id, rx, state).await
-    });
+                    let id = bridge.attach(Box::new(Framed::new(socket, WrcBusProtocolCodec::new()))).await;
 
-    id
-}
+                    println!("new connection on {path} attached as client {id}");
+                }
+            }
+        });
+    }
 
-async fn process<T>(id: usize, mut socket: ReadHalf<T>, state: Arc<Mutex<SharedState>>) where
-    T: AsyncRead
-{
-    let mut buffer = vec![0; 256];
+    println!("listening on {}", args.listen);
 
     loop {
-        let n = socket.read(&mut buffer).await.expect("read");
+        let (socket, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                println!("failed to accept connection on {}: {err}", args.listen);
+                tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                continue;
+            }
+        };
 
-        if n == 0 {
-            break;
+        if let Err(err) = socket.set_nodelay(true) {
+            println!("failed to set TCP_NODELAY for {addr}: {err}");
         }
 
-        //println!("{id}: {:x?}", &buffer[0..n]);
+        let id = bridge.attach(Box::new(Framed::new(socket, WrcBusProtocolCodec::new()))).await;
 
-        for (other_id, other_socket) in state.lock().await.writers.iter_mut() {
-            if *other_id == id { continue }
-            
-            if let Err(err) = other_socket.write_all(&buffer[0..n]).await {
-                println!("error while writing to socket for client {other_id}: {err}");
-            }
-        }
+        println!("new connection from {addr} attached as client {id}");
     }
-
-    // socket was closed, no longer broadcast to it
-    println!("client {id} disconnected");
-    state.lock().await.writers.remove(&id);
 }
@@ -0,0 +1,71 @@
+use std::{path::PathBuf, fs::File, io::BufWriter, time::{UNIX_EPOCH, Duration}};
+
+use anyhow::{Result, Context};
+
+use clap::{Parser, ValueEnum};
+use samsunghvac2mqtt::{capture::Capture, export::{ExportedFrame, FrameExporter, JsonlExporter, CsvExporter, PcapngExporter}};
+
+
+/// Convert a capture from the `dump` tool's text output (e.g. from `dumps/`) to a structured format
+///
+/// Notes in the capture are attached to the frame following them, and blank lines start a new section.
+/// The text format has no wall clock time, so timestamps count from the Unix epoch.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Capture to convert
+    capture: PathBuf,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Jsonl)]
+    format: Format,
+
+    /// Write the output to a file instead of stdout
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// One JSON object per frame
+    Jsonl,
+    Csv,
+    /// pcapng capture for Wireshark (link-layer type `USER0`)
+    Pcapng,
+}
+
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let capture = std::fs::read_to_string(&args.capture)
+        .with_context(|| format!("failed to read {}", args.capture.display()))?;
+
+    let capture = Capture::parse(&capture)
+        .with_context(|| format!("failed to parse {}", args.capture.display()))?;
+
+    let output: Box<dyn std::io::Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)
+            .with_context(|| format!("failed to create {}", path.display()))?)),
+        None => Box::new(std::io::stdout()),
+    };
+
+    let mut exporter: Box<dyn FrameExporter> = match args.format {
+        Format::Jsonl => Box::new(JsonlExporter::new(output)),
+        Format::Csv => Box::new(CsvExporter::new(output)),
+        Format::Pcapng => Box::new(PcapngExporter::new(output)),
+    };
+
+    for (i, captured) in capture.frames.iter().enumerate() {
+        exporter.export(&ExportedFrame {
+            timestamp: UNIX_EPOCH + Duration::from_millis(captured.elapsed_ms),
+            elapsed_ms: captured.elapsed_ms as u128,
+            delta_ms: captured.delta_ms as u128,
+            frame: &captured.frame,
+            section: capture.section_of(i),
+            notes: capture.notes_before(i)
+        })?;
+    }
+
+    Ok(())
+}
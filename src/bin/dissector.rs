@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, Context};
+
+use clap::{Parser};
+use samsunghvac2mqtt::{dissector::lua_dissector, protocol::layout::commands};
+
+
+/// Generate a Lua Wireshark dissector from the command definitions
+///
+/// Install it into the Wireshark plugins directory (e.g. ~/.local/lib/wireshark/plugins)
+/// to browse captures from `dump --format pcapng`.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Write the dissector to a file instead of stdout
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let lua = lua_dissector(&commands());
+
+    match &args.output {
+        Some(path) => std::fs::write(path, lua)
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => print!("{lua}"),
+    }
+
+    Ok(())
+}
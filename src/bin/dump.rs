@@ -1,11 +1,11 @@
-use std::{sync::Arc, pin::Pin, collections::HashMap, time::SystemTime, alloc::System};
+use std::{sync::Arc, pin::Pin, collections::HashMap, time::{SystemTime, Instant, Duration}, alloc::System, path::PathBuf, fs::File, io::BufWriter};
 
 use anyhow::{Result, bail, Context};
 
-use clap::{Parser};
+use clap::{Parser, ValueEnum};
 use colored::{Colorize, ColoredString};
 use futures::{Stream, StreamExt};
-use samsunghvac2mqtt::{config::Port, protocol::{codec::{RxFrame, delta_ms, FrameId}, addresses::*, commands::*}};
+use samsunghvac2mqtt::{config::{Port, PortStream}, export::{ExportedFrame, FrameExporter, JsonlExporter, CsvExporter, PcapngExporter}, filter::{ByteSet, FrameFilter}, stats::BusStats, recorder::{Recorder, RecorderOptions, Trigger}, reconnect::{ReconnectingPort, Backoff}, protocol::{codec::{RxFrame, FrameId}, addresses::*, commands::*, decode::decode}};
 use tokio::{net::{TcpListener, TcpStream}, sync::Mutex, io::{AsyncWriteExt, AsyncReadExt, AsyncWrite, AsyncRead, split, ReadHalf, WriteHalf}};
 use tokio_serial::SerialPortBuilderExt;
 use tokio_util::codec::Framed;
@@ -19,6 +19,113 @@ struct Args {
     /// 
     /// either serial:///device/path or tcp+raw://host:port URLs supported 
     port: Url,
+
+    /// Re-open the port (with exponential backoff) if it closes or fails
+    #[arg(long)]
+    reconnect: bool,
+
+    /// Decode the fields of known commands (unknown bits are highlighted)
+    #[arg(long)]
+    decode: bool,
+
+    /// Output format. The machine-readable formats always include the decoded fields
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Write the output to a file instead of stdout
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Only show frames from these addresses, e.g. `iu,85,50-5f`
+    ///
+    /// Addresses are hex values or ranges, or one of the classes main, sub, wrc, iu, erv,
+    /// broadcast or tracking
+    #[arg(long, value_parser = ByteSet::addresses)]
+    src: Option<ByteSet>,
+
+    /// Only show frames to these addresses (see --src)
+    #[arg(long, value_parser = ByteSet::addresses)]
+    dst: Option<ByteSet>,
+
+    /// Only show these commands, e.g. `52-55,a0`
+    ///
+    /// Commands are hex values or ranges, or one of the classes handover (c4, c5 and d1)
+    /// or tracking (f9-fe)
+    #[arg(long, value_parser = ByteSet::commands)]
+    cmd: Option<ByteSet>,
+
+    /// Hide these commands (see --cmd)
+    #[arg(long, value_parser = ByteSet::commands)]
+    exclude_cmd: Option<ByteSet>,
+
+    /// Hide replies identical to the previous one with the same source, destination and command
+    #[arg(long)]
+    only_changes: bool,
+
+    /// Print bus statistics (frame rates, reply latency, missing replies, errors) to stderr
+    ///
+    /// Statistics include every frame, regardless of filters.
+    #[arg(long)]
+    stats: bool,
+
+    /// How often to print the statistics (seconds). They are also printed when the port closes
+    #[arg(long, default_value_t = 60)]
+    stats_interval: u64,
+
+    /// Record every frame (regardless of filters) with a microsecond timestamp to a JSONL file
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Start a new recording file once the current one reaches this size (MB)
+    #[arg(long, requires = "record")]
+    record_max_mb: Option<u64>,
+
+    /// Start a new recording file once the current one covers this long (minutes)
+    #[arg(long, requires = "record")]
+    record_max_minutes: Option<u64>,
+
+    /// Only keep the last N minutes in memory, and write them out when a trigger frame is received
+    #[arg(long, requires = "record")]
+    ring_minutes: Option<u64>,
+
+    /// Frames that write out the ring buffer
+    #[arg(long = "trigger", value_enum, default_values_t = [TriggerArg::Checksum, TriggerArg::Reset], requires = "ring_minutes")]
+    triggers: Vec<TriggerArg>,
+
+    /// How long to keep recording after a trigger (seconds)
+    #[arg(long, default_value_t = 10, requires = "ring_minutes")]
+    post_trigger_secs: u64,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Coloured text (can be played back with a `replay://` port)
+    Text,
+    /// One JSON object per frame
+    Jsonl,
+    Csv,
+    /// pcapng capture for Wireshark (link-layer type `USER0`)
+    Pcapng,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum TriggerArg {
+    /// A frame with an invalid checksum
+    Checksum,
+    /// Bytes skipped to find the start of a frame
+    Resync,
+    /// A `0xd1` other than the main WRC's end of phase broadcast
+    Reset,
+}
+
+impl From<TriggerArg> for Trigger {
+    fn from(trigger: TriggerArg) -> Self {
+        match trigger {
+            TriggerArg::Checksum => Trigger::ChecksumError,
+            TriggerArg::Resync => Trigger::Resync,
+            TriggerArg::Reset => Trigger::Reset,
+        }
+    }
 }
 
 
@@ -26,7 +133,42 @@ struct Args {
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    let mut framed = Port::open(&args.port).await?.framed();
+    let mut exporter: Option<Box<dyn FrameExporter>> = if args.format == Format::Text {
+        if args.output.is_some() {
+            bail!("--output is only supported for the jsonl, csv and pcapng formats");
+        }
+        None
+    } else {
+        let output: Box<dyn std::io::Write> = match &args.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?)),
+            None => Box::new(std::io::stdout()),
+        };
+
+        Some(match args.format {
+            Format::Jsonl => Box::new(JsonlExporter::new(output)),
+            Format::Csv => Box::new(CsvExporter::new(output)),
+            Format::Pcapng => Box::new(PcapngExporter::new(output)),
+            Format::Text => unreachable!(),
+        })
+    };
+
+    let mut framed: Box<dyn PortStream> = if args.reconnect {
+        let port = ReconnectingPort::open(args.port.clone(), Backoff::default()).await?;
+
+        let mut events = port.subscribe();
+        let url = args.port.clone();
+        tokio::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                // keep stdout for the frames
+                eprintln!("{url}: {event:?}");
+            }
+        });
+
+        Box::new(port)
+    } else {
+        Port::open(&args.port).await?.framed()
+    };
 
     fn addr_desc(id: FrameId, addr: u8) -> String {
         let desc = match addr {
@@ -53,7 +195,7 @@ async fn main() -> Result<()> {
         format!("{addr:02x}: {desc: <18}")
     }
 
-    fn cmd_desc(id: FrameId, data: Vec<u8>) -> String {
+    fn cmd_desc(id: FrameId) -> String {
         let desc = match id.into() {
             (MAIN_WRC | SUB_WRC, _, Command52Request::ID) => "Info 1 Request".to_string(),
             (INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, MAIN_WRC | SUB_WRC, Command52IndoorUnitResponse::ID) => "IU Info 1 Response".to_string(),
@@ -76,7 +218,7 @@ async fn main() -> Result<()> {
             _ => format!("Unknown")
         };
         let cmd = id.cmd;
-        format!("{cmd:02x}: {desc: <20} {data:02x?}")
+        format!("{cmd:02x}: {desc: <20}")
     }
 
     fn coloured(id: FrameId, line: String) -> ColoredString {
@@ -93,14 +235,66 @@ async fn main() -> Result<()> {
         }
     }
 
-    let start_time = SystemTime::now();
-    let mut last_frame_time: Option<SystemTime> = None;
+    let mut filter = FrameFilter::default();
+    filter.src = args.src.clone();
+    filter.dst = args.dst.clone();
+    filter.cmd = args.cmd.clone();
+    filter.exclude_cmd = args.exclude_cmd.clone();
+    filter.only_changes = args.only_changes;
+
+    let mut stats = args.stats.then(BusStats::default);
+    let mut last_stats_time = Instant::now();
+
+    let mut recorder = args.record.as_ref().map(|path| Recorder::new(RecorderOptions {
+        path: path.clone(),
+        max_bytes: args.record_max_mb.map(|mb| mb * 1_000_000),
+        max_duration: args.record_max_minutes.map(|minutes| Duration::from_secs(minutes * 60)),
+        ring: args.ring_minutes.map(|minutes| Duration::from_secs(minutes * 60)),
+        post_trigger: Duration::from_secs(args.post_trigger_secs),
+        triggers: args.triggers.iter().copied().map(Trigger::from).collect()
+    }));
+
+    // timing is from a monotonic clock, so it doesn't jump with NTP
+    let start_time = Instant::now();
+    let mut last_frame_time: Option<Instant> = None;
 
     while let Some(Ok(frame)) = framed.next().await {
-        let start_delta_ms = delta_ms(Some(start_time));
-        let last_frame_delta_ms = delta_ms(last_frame_time);
+        if let Some(recorder) = &mut recorder {
+            recorder.record(&frame)?;
+        }
+
+        if let Some(stats) = &mut stats {
+            stats.add(start_time.elapsed().as_millis() as u64, &frame);
+
+            if last_stats_time.elapsed() >= Duration::from_secs(args.stats_interval) {
+                eprint!("{}", stats.report());
+                last_stats_time = Instant::now();
+            }
+        }
+
+        // the delta is to the last frame shown, so a filtered dump replays with the same timing
+        if !filter.matches(&frame) {
+            continue;
+        }
 
-        let (id, data) = match frame {
+        let start_delta_ms = start_time.elapsed().as_millis();
+        let last_frame_delta_ms = last_frame_time.map(|time| time.elapsed().as_millis()).unwrap_or_default();
+
+        if let Some(exporter) = &mut exporter {
+            exporter.export(&ExportedFrame {
+                timestamp: SystemTime::now(),
+                elapsed_ms: start_delta_ms,
+                delta_ms: last_frame_delta_ms,
+                frame: &frame,
+                section: 0,
+                notes: Vec::new()
+            })?;
+
+            last_frame_time = Some(Instant::now());
+            continue;
+        }
+
+        let (id, data) = match &frame {
             RxFrame::Long(frame) => (frame.id, frame.data.to_vec()),
             RxFrame::Short(frame) => (frame.id, frame.data.to_vec()),
             RxFrame::Corrupted(data) => {
@@ -112,13 +306,29 @@ async fn main() -> Result<()> {
 
         let src = addr_desc(id, id.src);
         let dst = addr_desc(id, id.dst);
-        let cmd = cmd_desc(id, data);
-
-        let line = format!("[{start_delta_ms:8}, {last_frame_delta_ms:8}] {src} -> {dst}: {cmd}");
+        let cmd = cmd_desc(id);
+
+        let line = format!("[{start_delta_ms:8}, {last_frame_delta_ms:8}] {src} -> {dst}: {cmd} {data:02x?}");
+
+        // the raw bytes are kept (so the output can still be replayed), unknown commands just aren't decoded
+        match decode(&frame).filter(|_| args.decode) {
+            Some(fields) => {
+                let mut line = coloured(id, line).to_string();
+                for field in fields {
+                    let text = coloured(id, format!(" {field}"));
+                    let text = if field.unknown { text.bright_yellow().bold() } else { text };
+                    line.push_str(&text.to_string());
+                }
+                println!("{line}");
+            },
+            None => println!("{}", coloured(id, line)),
+        }
 
-        println!("{}", coloured(id, line));
+        last_frame_time = Some(Instant::now());
+    }
 
-        last_frame_time = Some(SystemTime::now());
+    if let Some(stats) = &stats {
+        eprint!("{}", stats.report());
     }
 
 
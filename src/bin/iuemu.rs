@@ -19,16 +19,14 @@ struct Args {
     /// URL of the port to connect to
     /// 
     /// either serial:///device/path or tcp+raw://host:port URLs supported 
+    #[arg(default_value = "tcp+raw://localhost:3456")]
     port: Url,
 }
 
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    //let args = Args::parse();
-    let args = Args {
-        port: Url::parse("tcp+raw://localhost:3456")?
-    };
+    let args = Args::parse();
 
     let framed = Port::open(&args.port).await?.framed();
 
@@ -79,6 +77,7 @@ pub async fn indoor_unit_emulator(unit_id: u8, mut port: Box<dyn PortStream>) ->
         humidifier: false,
         oa_intake: false,
         away_mds: false,
+        ..Default::default()
     };
 
     /// is the address in the rx frame for a indoor unit?
@@ -148,7 +147,7 @@ pub async fn indoor_unit_emulator(unit_id: u8, mut port: Box<dyn PortStream>) ->
                 
                 ShortFrame::pack(address.into(), MAIN_WRC, resp)?.into()
             }
-            (MAIN_WRC, dst, TrackingAssignAddress::ID) if dst == address.into() => {
+            (MAIN_WRC, dst, TrackingAssignAddress::ID) if dst == u8::from(address) => {
                 let frame = frame.unpack_as::<TrackingAssignAddress>()?;
 
                 let resp = TrackingAddressAssigned {
@@ -21,17 +21,14 @@ struct Args {
     /// URL of the port to connect to
     /// 
     /// either serial:///device/path or tcp+raw://host:port URLs supported 
+    #[arg(default_value = "tcp+raw://localhost:3456")]
     port: Url,
 }
 
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    //let args = Args::parse();
-
-    let args = Args {
-        port: Url::parse("tcp+raw://localhost:3456")?
-    };
+    let args = Args::parse();
 
     let framed = Port::open(&args.port).await?.framed();
 
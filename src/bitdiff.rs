@@ -0,0 +1,299 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Write};
+
+use crate::{capture::{Annotation, Capture, CapturedFrame}, protocol::{codec::RxFrame, commands::{Command, CommandA0}, addresses::*, layout::{self, CommandLayout}}};
+
+
+/// Frames are grouped by source class, destination class and command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GroupKey {
+    pub src: AddressClass,
+    pub dst: AddressClass,
+    pub cmd: u8
+}
+
+/// A user-annotated event, e.g. "pressed swing on the remote".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub elapsed_ms: u64,
+    pub label: String
+}
+
+impl Event {
+    /// The notes in a capture as events, at the time of the frame following each note
+    /// (or the last frame, for notes at the end).
+    pub fn from_notes(capture: &Capture) -> Vec<Self> {
+        capture.markers.iter()
+            .filter_map(|marker| {
+                let Annotation::Note(note) = &marker.annotation else { return None };
+                let frame = capture.frames.get(marker.frame).or(capture.frames.last())?;
+
+                Some(Event { elapsed_ms: frame.elapsed_ms, label: note.clone() })
+            })
+            .collect()
+    }
+}
+
+/// A single bit changing between consecutive frames of the same source, destination and command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitChange {
+    pub elapsed_ms: u64,
+    pub src: u8,
+    pub dst: u8,
+
+    /// Bit position (msb0).
+    pub bit: usize,
+
+    /// The new value.
+    pub value: bool
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BitStats {
+    pub changes: usize,
+
+    /// Number of frames with the bit set.
+    pub set: usize
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Group {
+    pub frames: usize,
+
+    /// Data size in bytes.
+    pub size: usize,
+
+    pub bits: Vec<BitStats>,
+    pub changes: Vec<BitChange>,
+
+    /// Field layout of the command, if known.
+    pub layout: Option<CommandLayout>,
+
+    /// Data of the last frame, by (src, dst).
+    last: HashMap<(u8, u8), Vec<u8>>
+}
+
+impl Group {
+    /// Name of the field a bit belongs to, e.g. `mode, bits 36:39`.
+    pub fn bit_name(&self, bit: usize) -> Option<String> {
+        let field = self.layout.as_ref()?.fields.iter().find(|field| field.bits.contains(&bit))?;
+
+        Some(if field.bits.start() == field.bits.end() {
+            field.name.clone()
+        } else {
+            format!("{}, bits {}:{}", field.name, field.bits.start(), field.bits.end())
+        })
+    }
+}
+
+/// An `0xa0` settings change sent to an IU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SettingsChange {
+    pub elapsed_ms: u64,
+    pub src: u8,
+    pub dst: u8
+}
+
+fn bit_value(data: &[u8], bit: usize) -> bool {
+    data[bit / 8] & (0x80 >> (bit % 8)) != 0
+}
+
+/// Tracks which bits change across a capture, to help work out the meaning of unknown fields.
+///
+/// Each change is correlated with the last `0xa0` settings change sent to the unit,
+/// and the last annotated event, within a window before it.
+#[derive(Default)]
+pub struct BitDiff {
+    pub groups: BTreeMap<GroupKey, Group>,
+    pub settings_changes: Vec<SettingsChange>,
+    pub events: Vec<Event>,
+
+    layouts: Vec<CommandLayout>
+}
+
+impl BitDiff {
+    pub fn new(mut events: Vec<Event>) -> Self {
+        events.sort_by_key(|event| event.elapsed_ms);
+
+        Self {
+            events,
+            layouts: layout::commands(),
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, captured: &CapturedFrame) {
+        let (id, data) = match &captured.frame {
+            RxFrame::Long(frame) => (frame.id, &frame.data[..]),
+            RxFrame::Short(frame) => (frame.id, &frame.data[..]),
+            RxFrame::Corrupted(_) => return
+        };
+
+        if id.cmd == CommandA0::ID && AddressClass::of(id.dst) == AddressClass::IndoorUnit {
+            self.settings_changes.push(SettingsChange { elapsed_ms: captured.elapsed_ms, src: id.src, dst: id.dst });
+        }
+
+        let key = GroupKey { src: AddressClass::of(id.src), dst: AddressClass::of(id.dst), cmd: id.cmd };
+
+        let group = self.groups.entry(key).or_insert_with(|| Group {
+            size: data.len(),
+            bits: vec![BitStats::default(); data.len() * 8],
+            layout: self.layouts.iter()
+                .find(|layout| layout.matches(id.src, id.dst, id.cmd, data.len()))
+                .cloned(),
+            ..Default::default()
+        });
+
+        if data.len() != group.size {
+            return
+        }
+
+        group.frames += 1;
+
+        for bit in 0..data.len() * 8 {
+            if bit_value(data, bit) {
+                group.bits[bit].set += 1;
+            }
+        }
+
+        if let Some(last) = group.last.insert((id.src, id.dst), data.to_vec()) {
+            for bit in 0..data.len() * 8 {
+                let value = bit_value(data, bit);
+
+                if value != bit_value(&last, bit) {
+                    group.bits[bit].changes += 1;
+                    group.changes.push(BitChange { elapsed_ms: captured.elapsed_ms, src: id.src, dst: id.dst, bit, value });
+                }
+            }
+        }
+    }
+
+    /// The last settings change sent to the IU involved in `change`, within `window_ms` before it.
+    pub fn settings_change_before(&self, change: &BitChange, window_ms: u64) -> Option<&SettingsChange> {
+        self.settings_changes.iter().rev()
+            .filter(|settings| settings.elapsed_ms <= change.elapsed_ms && change.elapsed_ms - settings.elapsed_ms <= window_ms)
+            .find(|settings| settings.dst == change.src || settings.dst == change.dst)
+    }
+
+    /// The last event within `window_ms` before `change`.
+    pub fn event_before(&self, change: &BitChange, window_ms: u64) -> Option<&Event> {
+        self.events.iter().rev()
+            .find(|event| event.elapsed_ms <= change.elapsed_ms && change.elapsed_ms - event.elapsed_ms <= window_ms)
+    }
+
+    /// A per-group summary of the bits that changed, followed by a timeline of the changes
+    /// to bits that changed at most `max_changes` times (i.e., not temperatures and counters).
+    pub fn report(&self, window_ms: u64, max_changes: usize) -> String {
+        let mut report = String::new();
+
+        for (key, group) in &self.groups {
+            let name = group.layout.as_ref().map(|layout| layout.name).unwrap_or("unknown command");
+            writeln!(report, "{} -> {} {:02x} ({name}): {} frames", key.src.name(), key.dst.name(), key.cmd, group.frames).unwrap();
+
+            for (bit, stats) in group.bits.iter().enumerate().filter(|(_, stats)| stats.changes > 0) {
+                let changes: Vec<_> = group.changes.iter().filter(|change| change.bit == bit).collect();
+                let after_settings = changes.iter().filter(|change| self.settings_change_before(change, window_ms).is_some()).count();
+                let after_event = changes.iter().filter(|change| self.event_before(change, window_ms).is_some()).count();
+
+                write!(report, "  bit {bit:2}").unwrap();
+                if let Some(name) = group.bit_name(bit) {
+                    write!(report, " ({name})").unwrap();
+                }
+                writeln!(report, ": {} changes, set in {}/{} frames, {after_settings} after 0xa0, {after_event} after an event",
+                    stats.changes, stats.set, group.frames).unwrap();
+            }
+        }
+
+        writeln!(report).unwrap();
+        writeln!(report, "timeline:").unwrap();
+
+        let mut timeline: Vec<_> = self.groups.iter()
+            .flat_map(|(key, group)| group.changes.iter()
+                .filter(|change| group.bits[change.bit].changes <= max_changes)
+                .map(move |change| (key, group, change)))
+            .collect();
+        timeline.sort_by_key(|(_, _, change)| change.elapsed_ms);
+
+        let mut events = self.events.iter().peekable();
+
+        for (key, group, change) in timeline {
+            while let Some(event) = events.next_if(|event| event.elapsed_ms <= change.elapsed_ms) {
+                writeln!(report, "{:10} ** {}", event.elapsed_ms, event.label).unwrap();
+            }
+
+            write!(report, "{:10} {:02x} -> {:02x} {:02x} bit {:2} -> {}", change.elapsed_ms, change.src, change.dst, key.cmd, change.bit, change.value as u8).unwrap();
+            if let Some(name) = group.bit_name(change.bit) {
+                write!(report, " ({name})").unwrap();
+            }
+            if let Some(settings) = self.settings_change_before(change, window_ms) {
+                write!(report, " [{} ms after 0xa0 from {:02x}]", change.elapsed_ms - settings.elapsed_ms, settings.src).unwrap();
+            }
+            writeln!(report).unwrap();
+        }
+
+        for event in events {
+            writeln!(report, "{:10} ** {}", event.elapsed_ms, event.label).unwrap();
+        }
+
+        report
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::codec::LongFrame;
+
+    use super::*;
+
+    fn captured(elapsed_ms: u64, src: u8, dst: u8, cmd: u8, data: [u8; 8]) -> CapturedFrame {
+        CapturedFrame { elapsed_ms, delta_ms: 0, frame: RxFrame::Long(LongFrame::new(src, dst, cmd, data)) }
+    }
+
+    #[test]
+    fn test_bit_diff() {
+        let mut diff = BitDiff::new(vec![Event { elapsed_ms: 1500, label: "pressed swing".to_string() }]);
+
+        let response = |elapsed_ms, byte4| captured(elapsed_ms, 0x20, MAIN_WRC, 0x52, [0x4a, 0x4c, 0x4e, 0xf8, byte4, 0x10, 0x00, 0x4e]);
+
+        diff.add(&response(1000, 0x22));
+        diff.add(&captured(1100, 0x21, MAIN_WRC, 0x52, [0x4a, 0x4c, 0x4e, 0xf8, 0x00, 0x10, 0x00, 0x4e]));
+        diff.add(&response(2000, 0x22));
+        diff.add(&captured(2100, MAIN_WRC, 0x20, CommandA0::ID, [0x1f, 0x18, 0x18, 0x01, 0xf4, 0x00, 0x00, 0x00]));
+        diff.add(&response(2200, 0x62));
+
+        let key = GroupKey { src: AddressClass::IndoorUnit, dst: AddressClass::MainWrc, cmd: 0x52 };
+        let group = &diff.groups[&key];
+
+        // IUs are compared with their own previous frame
+        assert_eq!(group.frames, 4);
+        assert_eq!(group.changes, [BitChange { elapsed_ms: 2200, src: 0x20, dst: MAIN_WRC, bit: 33, value: true }]);
+        assert_eq!(group.bits[33], BitStats { changes: 1, set: 1 });
+        assert_eq!(group.bit_name(33).as_deref(), Some("unknown_bit33"));
+        assert_eq!(group.bit_name(37).as_deref(), Some("mode, bits 36:39"));
+
+        let change = &group.changes[0];
+        assert_eq!(diff.settings_change_before(change, 500).map(|settings| settings.dst), Some(0x20));
+        assert_eq!(diff.event_before(change, 500), None);
+        assert_eq!(diff.event_before(change, 1000).map(|event| event.label.as_str()), Some("pressed swing"));
+
+        let report = diff.report(1000, 10);
+        assert!(report.contains("iu -> main 52 (Command52IndoorUnitResponse): 4 frames\n  bit 33 (unknown_bit33): 1 changes, set in 1/4 frames, 1 after 0xa0, 1 after an event\n"));
+        assert!(report.contains("      1500 ** pressed swing\n      2200 20 -> 84 52 bit 33 -> 1 (unknown_bit33) [100 ms after 0xa0 from 84]\n"));
+    }
+    #[test]
+    fn test_events_from_notes() {
+        let capture = Capture::parse(concat!(
+            "- physical main + sub wrc\n",
+            "\n",
+            "[   91151,     8595] 84: Main WRC           -> eb: Tracking Broadcast: fb: Unknown              [02, 00, 00, 00, 00, 00, 00, 00]\n",
+            "\n",
+            "pressed swing\n",
+            "[  199801,      357] 84: Main WRC           -> 20: Indoor Unit 0     : 52: Info 1 Request       [00, 00, 00, 00, 00, 00, 00, 00]\n",
+            "IU powered off here\n",
+        )).unwrap();
+
+        let events = Event::from_notes(&capture);
+        let events: Vec<_> = events.iter().map(|event| (event.elapsed_ms, event.label.as_str())).collect();
+        assert_eq!(events, [(91151, "- physical main + sub wrc"), (199801, "pressed swing"), (199801, "IU powered off here")]);
+    }
+}
@@ -0,0 +1,140 @@
+use std::{sync::Arc, collections::HashMap};
+
+use futures::{SinkExt, StreamExt};
+use tokio::sync::{Mutex, mpsc::{self, error::TrySendError}};
+
+use crate::{config::PortStream, protocol::codec::{RxFrame, TxFrame}};
+
+
+/// Frames queued for a client before it's considered too slow and frames to it are dropped.
+const QUEUE_SIZE: usize = 32;
+
+#[derive(Default)]
+struct Clients {
+    next_id: usize,
+    queues: HashMap<usize, mpsc::Sender<TxFrame>>
+}
+
+/// Connects ports (serial ports, sockets, etc.) into a single bus.
+///
+/// Each port is decoded with its own codec, and only whole, valid frames are forwarded to
+/// the other ports, so frames from different clients can't interleave. Every port has its own
+/// write task, so a slow or stuck client only drops its own frames instead of stalling the bus.
+#[derive(Clone, Default)]
+pub struct Bridge {
+    clients: Arc<Mutex<Clients>>
+}
+
+impl Bridge {
+    /// Attach a port to the bus, returning its client id.
+    pub async fn attach(&self, port: Box<dyn PortStream>) -> usize {
+        let (mut sink, mut stream) = port.split();
+        let (queue, mut frames) = mpsc::channel::<TxFrame>(QUEUE_SIZE);
+
+        let id = {
+            let mut clients = self.clients.lock().await;
+
+            let id = clients.next_id;
+            clients.next_id += 1;
+            clients.queues.insert(id, queue);
+
+            id
+        };
+
+        tokio::spawn(async move {
+            while let Some(frame) = frames.recv().await {
+                if let Err(err) = sink.send(frame).await {
+                    println!("error while writing to client {id}: {err}");
+                    break;
+                }
+            }
+        });
+
+        let bridge = self.clone();
+        tokio::spawn(async move {
+            while let Some(frame) = stream.next().await {
+                match frame {
+                    Ok(RxFrame::Long(frame)) => bridge.forward(id, TxFrame::Long(frame)).await,
+                    Ok(RxFrame::Short(frame)) => bridge.forward(id, TxFrame::Short(frame)).await,
+                    Ok(RxFrame::Corrupted(data)) => println!("client {id}: dropped corrupted frame {data:02x?}"),
+                    Err(err) => {
+                        println!("error while reading from client {id}: {err}");
+                        break;
+                    }
+                }
+            }
+
+            // the port was closed, no longer broadcast to it (this also ends its write task)
+            println!("client {id} disconnected");
+            bridge.clients.lock().await.queues.remove(&id);
+        });
+
+        id
+    }
+
+    /// Queue a frame from client `from` to every other client.
+    async fn forward(&self, from: usize, frame: TxFrame) {
+        let clients = self.clients.lock().await;
+
+        for (id, queue) in clients.queues.iter().filter(|(id, _)| **id != from) {
+            match queue.try_send(frame.clone()) {
+                Ok(()) => {},
+                Err(TrySendError::Full(_)) => println!("client {id} is too slow, dropped a frame"),
+                // disconnected, removed once its reader stops
+                Err(TrySendError::Closed(_)) => {},
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::Framed;
+
+    use crate::protocol::codec::{WrcBusProtocolCodec, LongFrame, ShortFrame};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bridge_forwards_whole_frames() {
+        let bridge = Bridge::default();
+
+        let (a, mut a_remote) = duplex(256);
+        let (b, mut b_remote) = duplex(256);
+        let (c, mut c_remote) = duplex(256);
+
+        for port in [a, b, c] {
+            bridge.attach(Box::new(Framed::new(port, WrcBusProtocolCodec::new()))).await;
+        }
+
+        let long_frame = LongFrame::new(0x20, 0x84, 0x52, [0x4b, 0x4c, 0x63, 0xf8, 0x81, 0x10, 0x00, 0x6f]).to_bytes();
+        let short_frame = ShortFrame::new(0x84, 0xeb, 0xf9, [0x00]).to_bytes();
+
+        // a's frame arrives in pieces, with b's frame in between
+        a_remote.write_all(&[0x01, 0x02]).await.unwrap();
+        a_remote.write_all(&long_frame[..5]).await.unwrap();
+        tokio::task::yield_now().await;
+        b_remote.write_all(&short_frame).await.unwrap();
+
+        let mut received = [0; 7];
+        c_remote.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, &short_frame[..]);
+
+        a_remote.write_all(&long_frame[5..]).await.unwrap();
+
+        let mut received = [0; 14];
+        c_remote.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, &long_frame[..]);
+
+        // each client gets the other clients' frames, but not its own (or the junk)
+        let mut received = [0; 14];
+        b_remote.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, &long_frame[..]);
+
+        let mut received = [0; 7];
+        a_remote.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, &short_frame[..]);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{inventory::BusInventory, protocol::{codec::{RxFrame, Unpack}, commands::*, addresses::*, iu::{IndoorUnitState, IndoorUnitCapabilities}}};
+
+
+/// The bus topology and last known IU states, saved to a state file so they can be
+/// restored when the sub WRC restarts.
+///
+/// Restored values are stale until the IUs are polled again.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BusCache {
+    pub inventory: BusInventory,
+
+    /// Detected capabilities (including option codes), by IU.
+    pub capabilities: BTreeMap<u8, IndoorUnitCapabilities>,
+
+    /// Last known states, by IU.
+    pub states: BTreeMap<u8, IndoorUnitState>,
+}
+
+impl BusCache {
+    /// Load the cache from a state file. Returns `None` if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("failed to read state file {}", path.display()))
+        };
+
+        let cache = serde_json::from_str(&text)
+            .with_context(|| format!("invalid state file {}", path.display()))?;
+
+        Ok(Some(cache))
+    }
+
+    /// Save the cache to a state file, replacing it atomically.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, text)
+            .with_context(|| format!("failed to write state file {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("failed to replace state file {}", path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inventory.indoor_units.is_empty() && self.inventory.ervs.is_empty()
+    }
+
+    /// Forget the units that aren't in `inventory`.
+    pub fn retain(&mut self, inventory: &BusInventory) {
+        self.inventory.indoor_units.retain(|address| inventory.indoor_units.contains(address));
+        self.inventory.ervs.retain(|address| inventory.ervs.contains(address));
+        self.capabilities.retain(|address, _| inventory.indoor_units.contains(address));
+        self.states.retain(|address, _| inventory.indoor_units.contains(address));
+    }
+}
+
+/// The number of IUs on the bus, as reported by the main WRC in its `0xc6` broadcasts
+/// and `0xc4` handovers during normal operation.
+pub fn reported_indoor_units(frame: &RxFrame) -> Option<u8> {
+    match frame.id()?.into() {
+        (MAIN_WRC, ENUMERATION_BROADCAST, CommandC6::ID) => {
+            Some(frame.unpack_unchecked::<CommandC6>().ok()?.number_of_indoor_units)
+        },
+
+        (MAIN_WRC, SUB_WRC, CommandC4Request::ID) => {
+            let cmd = frame.unpack_unchecked::<CommandC4Request>().ok()?;
+
+            // `0x00` during enumeration, when the count may be incomplete
+            (cmd.unknown_byte0 == 0x01).then_some(cmd.number_of_indoor_units)
+        },
+
+        _ => None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::protocol::{codec::LongFrame, iu::IndoorUnitMode};
+
+    use super::*;
+
+    #[test]
+    fn test_bus_cache() {
+        let path = std::env::temp_dir().join(format!("samsunghvac2mqtt-test-{}.json", std::process::id()));
+
+        assert!(BusCache::load(&path).unwrap().is_none());
+
+        let cache = BusCache {
+            inventory: BusInventory { indoor_units: BTreeSet::from([0x20, 0x21]), ervs: BTreeSet::from([0x50]) },
+            capabilities: BTreeMap::from([(0x20, IndoorUnitCapabilities { s_plasma_ion: Some(true), ..Default::default() })]),
+            states: BTreeMap::from([(0x20, IndoorUnitState { power: true, mode: IndoorUnitMode::Cool, setpoint_temp: 23, ..Default::default() })]),
+        };
+
+        cache.save(&path).unwrap();
+        assert_eq!(BusCache::load(&path).unwrap(), Some(cache));
+
+        std::fs::remove_file(&path).unwrap();
+
+        let frame = RxFrame::Long(LongFrame::new(MAIN_WRC, ENUMERATION_BROADCAST, CommandC6::ID, [0x22, 0x02, 0x20, 0xb0, 0, 0, 0, 0]));
+        assert_eq!(reported_indoor_units(&frame), Some(2));
+
+        let frame = RxFrame::Long(LongFrame::new(MAIN_WRC, SUB_WRC, CommandC4Request::ID, [0x00, 0x01, 0, 0, 0x20, 0, 0, 0x44]));
+        assert_eq!(reported_indoor_units(&frame), None);
+    }
+}
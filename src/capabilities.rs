@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::protocol::{codec::{RxFrame, Unpack}, commands::*, addresses::*, iu::{IndoorUnitCapabilities, OptionCode}};
+
+
+/// A feature whose on/off bit is set is supported. A clear bit says nothing about support.
+fn confirm(feature: &mut Option<bool>, bit: bool) {
+    if bit {
+        *feature = Some(true);
+    }
+}
+
+/// Detects the features of each IU on the bus by passively watching its replies to either WRC.
+///
+/// The `0x53`/`0x54` feature bits are the features' on/off state, so a feature is confirmed once
+/// its bit has been seen set (since the start of the last bus tracking), and otherwise left unknown.
+/// The option code is read from the IU's EEPROM at addresses `0x00`/`0x01` with `0x70` (low bytes)
+/// and `0x71` (high bytes). It's recorded for when its encoding is known, but not yet used.
+/// An IU that answers `0x55` has humidity and CO2 readings.
+#[derive(Default)]
+pub struct CapabilityDetector {
+    capabilities: BTreeMap<u8, IndoorUnitCapabilities>,
+
+    /// EEPROM address of the last read request, by (WRC, IU, command).
+    eeprom_requests: HashMap<(u8, u8, u8), u8>,
+
+    /// Option code halves read so far, by IU.
+    option_code_reads: HashMap<u8, (Option<[u8; 8]>, Option<[u8; 8]>)>,
+}
+
+impl CapabilityDetector {
+    /// Capabilities detected so far for an IU.
+    pub fn capabilities(&self, address: u8) -> Option<&IndoorUnitCapabilities> {
+        self.capabilities.get(&address)
+    }
+
+    /// Restore previously detected capabilities of an IU (e.g., from a [crate::cache::BusCache]).
+    ///
+    /// Newly seen feature bits are added to them.
+    pub fn restore(&mut self, address: u8, capabilities: IndoorUnitCapabilities) {
+        self.capabilities.insert(address, capabilities);
+    }
+
+    pub fn forget(&mut self, address: u8) {
+        self.capabilities.remove(&address);
+        self.option_code_reads.remove(&address);
+    }
+
+    /// Observe a frame. Returns the IU's capabilities if they changed.
+    pub fn observe(&mut self, frame: &RxFrame) -> Option<(u8, IndoorUnitCapabilities)> {
+        let id = frame.id()?;
+
+        let address = match id.into() {
+            (MAIN_WRC, TRACKING_BROADCAST, TrackingCommandFB::ID) => {
+                if let TrackingDiscoverState::TrackingStart = frame.unpack_unchecked::<TrackingCommandFB>().ok()?.state {
+                    self.capabilities.clear();
+                    self.option_code_reads.clear();
+                }
+
+                return None
+            },
+
+            (wrc @ (MAIN_WRC | SUB_WRC), iu @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, EepromLowReadRequest::ID) => {
+                let req = frame.unpack_unchecked::<EepromLowReadRequest>().ok()?;
+                self.eeprom_requests.insert((wrc, iu, id.cmd), req.address);
+                return None
+            },
+
+            (wrc @ (MAIN_WRC | SUB_WRC), iu @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, EepromHighReadRequest::ID) => {
+                let req = frame.unpack_unchecked::<EepromHighReadRequest>().ok()?;
+                self.eeprom_requests.insert((wrc, iu, id.cmd), req.address);
+                return None
+            },
+
+            (src @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, MAIN_WRC | SUB_WRC, _) => src,
+
+            _ => return None
+        };
+
+        let previous = self.capabilities.get(&address).cloned();
+        let mut capabilities = previous.clone().unwrap_or_default();
+
+        match id.cmd {
+            Command53IndoorUnitResponse::ID => {
+                let resp = frame.unpack_unchecked::<Command53IndoorUnitResponse>().ok()?;
+                confirm(&mut capabilities.humidifier, resp.humidifier);
+            },
+
+            Command54IndoorUnitResponse::ID => {
+                let resp = frame.unpack_unchecked::<Command54IndoorUnitResponse>().ok()?;
+                confirm(&mut capabilities.oa_intake, resp.oa_intake);
+                confirm(&mut capabilities.away_mds, resp.away_mds);
+                confirm(&mut capabilities.s_plasma_ion, resp.s_plasma_ion);
+                confirm(&mut capabilities.range_hood, resp.range_hood);
+                confirm(&mut capabilities.discharge_temp_control, resp.discharge_temp_control);
+            },
+
+            Command55IndoorUnitResponse::ID => {
+                capabilities.humidity_co2 = true;
+            },
+
+            EepromLowReadResponse::ID | EepromHighReadResponse::ID => {
+                let Some(0x00 | 0x01) = self.eeprom_requests.remove(&(id.dst, address, id.cmd)) else { return None };
+
+                let reads = self.option_code_reads.entry(address).or_default();
+
+                if id.cmd == EepromLowReadResponse::ID {
+                    reads.0 = Some(frame.unpack_unchecked::<EepromLowReadResponse>().ok()?.data);
+                } else {
+                    reads.1 = Some(frame.unpack_unchecked::<EepromHighReadResponse>().ok()?.data);
+                }
+
+                if let (Some(low), Some(high)) = *reads {
+                    let mut code = [0u8; 10];
+                    code[..8].copy_from_slice(&low);
+                    code[8..].copy_from_slice(&high[..2]);
+
+                    capabilities.option_code = Some(OptionCode(code));
+                }
+            },
+
+            _ => return None
+        }
+
+        if previous.as_ref() == Some(&capabilities) {
+            return None
+        }
+
+        self.capabilities.insert(address, capabilities.clone());
+
+        Some((address, capabilities))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{capture::parse_frames, controller::{IndoorUnitOption, Setting}, protocol::{codec::LongFrame, iu::IndoorUnitField}};
+
+    use super::*;
+
+    #[test]
+    fn test_capabilities_capture() {
+        let capture = std::fs::read_to_string("dumps/wrc-main+sub-boot-1IU.txt").unwrap();
+
+        let mut detector = CapabilityDetector::default();
+
+        for captured in parse_frames(&capture).unwrap() {
+            detector.observe(&captured.frame);
+        }
+
+        let capabilities = detector.capabilities(0x20).unwrap();
+        assert_eq!(capabilities.option_code.unwrap().to_string(), "b160cc45e477887c0056");
+
+        // both are switched off in the capture, which doesn't mean they aren't there
+        assert_eq!(capabilities.s_plasma_ion, None);
+        assert_eq!(capabilities.humidifier, None);
+        assert!(capabilities.supports(IndoorUnitField::SPlasmaIon));
+        assert!(capabilities.supports(IndoorUnitField::Humidifier));
+    }
+
+    #[test]
+    fn test_capabilities_feature_switched_off() {
+        let mut detector = CapabilityDetector::default();
+
+        let reply_54 = |data| RxFrame::Long(LongFrame::new(0x20, MAIN_WRC, Command54IndoorUnitResponse::ID, data));
+        let s_plasma_ion_on = [0, 0, 0, 0, 0, 0, 0, 0x10];
+
+        // an IU with S-Plasma ion, switched off: can still be switched on
+        detector.observe(&reply_54([0; 8]));
+        let capabilities = detector.capabilities(0x20).unwrap();
+        assert!(capabilities.supports(IndoorUnitField::SPlasmaIon));
+        assert!(Setting::Option(IndoorUnitOption::SPlasmaIon, true).supported_by(capabilities));
+
+        // switched on, it's confirmed, and stays so once it's switched off again
+        let (address, capabilities) = detector.observe(&reply_54(s_plasma_ion_on)).unwrap();
+        assert_eq!((address, capabilities.s_plasma_ion), (0x20, Some(true)));
+
+        assert!(detector.observe(&reply_54([0; 8])).is_none());
+        assert_eq!(detector.capabilities(0x20).unwrap().s_plasma_ion, Some(true));
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{Result, Context, bail};
+
+use crate::protocol::codec::{RxFrame, LongFrame, ShortFrame};
+
+
+/// A frame read from a capture in the `dump` tool's text output format.
+#[derive(Clone, Debug)]
+pub struct CapturedFrame {
+    /// Milliseconds since the start of the capture.
+    pub elapsed_ms: u64,
+
+    /// Milliseconds since the previous frame.
+    pub delta_ms: u64,
+
+    pub frame: RxFrame
+}
+
+/// Remove ANSI escape sequences (i.e., colours) from a line.
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip "ESC [ ... <final byte>"
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() { break }
+            }
+            continue;
+        }
+
+        stripped.push(c);
+    }
+
+    stripped
+}
+
+fn parse_hex(s: &str) -> Result<u8> {
+    u8::from_str_radix(s.trim(), 16)
+        .with_context(|| format!("invalid hex byte \"{s}\""))
+}
+
+/// Parse a single line of `dump` output, e.g.
+///
+/// `[   91151,     8595] 84: Main WRC           -> eb: Tracking Broadcast: fb: Unknown              [02, 00, 00, 00, 00, 00, 00, 00]`
+///
+/// Returns `Ok(None)` for lines that aren't frames (notes, blank lines, etc).
+pub fn parse_frame_line(line: &str) -> Result<Option<CapturedFrame>> {
+    let line = strip_ansi(line);
+    let line = line.trim();
+
+    let Some(line) = line.strip_prefix('[') else { return Ok(None) };
+
+    let (timestamps, rest) = line.split_once(']')
+        .context("missing ] after timestamps")?;
+
+    let (elapsed_ms, delta_ms) = timestamps.split_once(',')
+        .context("expected \"elapsed, delta\" timestamps")?;
+
+    let elapsed_ms = elapsed_ms.trim().parse().context("invalid elapsed timestamp")?;
+    let delta_ms = delta_ms.trim().parse().context("invalid delta timestamp")?;
+
+    let (src, dst) = rest.split_once(" -> ")
+        .context("expected \"src -> dst\"")?;
+
+    let src = parse_hex(src.split(':').next().unwrap_or_default())?;
+
+    // dst: desc: cmd: desc [data]
+    let mut fields = dst.splitn(4, ':');
+    let dst = parse_hex(fields.next().unwrap_or_default())?;
+    let cmd = parse_hex(fields.nth(1).context("missing command")?)?;
+
+    let data = fields.next().context("missing frame data")?;
+    let data = data.rfind('[')
+        .and_then(|start| Some(&data[start + 1..start + data[start..].find(']')?]))
+        .context("missing [frame data]")?;
+
+    let data = data.split(',')
+        .map(parse_hex)
+        .collect::<Result<Vec<u8>>>()?;
+
+    let frame = match data.len() {
+        1 => RxFrame::Short(ShortFrame::new(src, dst, cmd, [data[0]])),
+        8 => RxFrame::Long(LongFrame::new(src, dst, cmd, data.try_into().unwrap())),
+        other => bail!("unexpected frame data length {other}")
+    };
+
+    Ok(Some(CapturedFrame {
+        elapsed_ms,
+        delta_ms,
+        frame
+    }))
+}
+
+/// An annotation in a capture, between frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Annotation {
+    /// A free-form line, e.g. `- IU 0x20 is physical`.
+    Note(String),
+
+    /// One or more blank lines, e.g. between boot phases.
+    SectionBreak
+}
+
+/// An annotation, and where it is in the capture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Marker {
+    /// Index of the frame following the annotation (`frames.len()` at the end of the capture).
+    pub frame: usize,
+
+    pub annotation: Annotation
+}
+
+/// A capture in the `dump` tool's text output format, as found in `dumps/`.
+///
+/// These have free-form notes at the top, and blank lines between sections.
+#[derive(Clone, Debug, Default)]
+pub struct Capture {
+    pub frames: Vec<CapturedFrame>,
+    pub markers: Vec<Marker>
+}
+
+impl Capture {
+    pub fn parse(capture: &str) -> Result<Self> {
+        let mut parsed = Self::default();
+
+        for (i, line) in capture.lines().enumerate() {
+            let context = || format!("line {}: {line}", i + 1);
+
+            if let Some(frame) = parse_frame_line(line).with_context(context)? {
+                parsed.frames.push(frame);
+                continue;
+            }
+
+            let stripped = strip_ansi(line);
+            let stripped = stripped.trim();
+
+            if let Some(data) = stripped.strip_prefix("corrupted frame:") {
+                // logged without timestamps, so use the previous frame's
+                let data = data.trim().strip_prefix('[').and_then(|data| data.strip_suffix(']'))
+                    .context("missing [frame data]").with_context(context)?;
+                let data = data.split(',')
+                    .filter(|byte| !byte.trim().is_empty())
+                    .map(parse_hex)
+                    .collect::<Result<Vec<u8>>>().with_context(context)?;
+
+                let elapsed_ms = parsed.frames.last().map(|frame| frame.elapsed_ms).unwrap_or_default();
+                parsed.frames.push(CapturedFrame { elapsed_ms, delta_ms: 0, frame: RxFrame::Corrupted(data) });
+                continue;
+            }
+
+            let annotation = if stripped.is_empty() {
+                let repeated = parsed.markers.last()
+                    .is_some_and(|marker| marker.frame == parsed.frames.len() && marker.annotation == Annotation::SectionBreak);
+
+                // blank lines between the notes at the top aren't sections
+                if parsed.frames.is_empty() || repeated {
+                    continue;
+                }
+                Annotation::SectionBreak
+            } else {
+                Annotation::Note(stripped.to_string())
+            };
+
+            parsed.markers.push(Marker { frame: parsed.frames.len(), annotation });
+        }
+
+        // trailing blank lines
+        if parsed.markers.last().is_some_and(|marker| marker.frame == parsed.frames.len() && marker.annotation == Annotation::SectionBreak) {
+            parsed.markers.pop();
+        }
+
+        Ok(parsed)
+    }
+
+    /// The notes directly before a frame (for the first frame, the notes at the top of the capture).
+    pub fn notes_before(&self, frame: usize) -> Vec<&str> {
+        self.markers.iter()
+            .filter(|marker| marker.frame == frame)
+            .filter_map(|marker| match &marker.annotation {
+                Annotation::Note(note) => Some(note.as_str()),
+                Annotation::SectionBreak => None,
+            })
+            .collect()
+    }
+
+    /// The section a frame is in, counting from 0.
+    pub fn section_of(&self, frame: usize) -> usize {
+        self.markers.iter()
+            .filter(|marker| marker.frame <= frame && marker.annotation == Annotation::SectionBreak)
+            .count()
+    }
+}
+
+/// Parse all frames from a capture.
+pub fn parse_frames(capture: &str) -> Result<Vec<CapturedFrame>> {
+    Ok(Capture::parse(capture)?.frames)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_frame_line() {
+        let line = "[  199933,      131] 20: Indoor Unit 0      -> 84: Main WRC          : 52: IU Info 1 Response   [4b, 4d, 4f, f8, 81, 10, 00, 4e]";
+
+        let captured = parse_frame_line(line).unwrap().unwrap();
+        assert_eq!(captured.elapsed_ms, 199933);
+        assert_eq!(captured.delta_ms, 131);
+
+        let RxFrame::Long(frame) = captured.frame else { panic!("expected a long frame") };
+        assert_eq!((frame.id.src, frame.id.dst, frame.id.cmd), (0x20, 0x84, 0x52));
+        assert_eq!(frame.data, [0x4b, 0x4d, 0x4f, 0xf8, 0x81, 0x10, 0x00, 0x4e]);
+
+        let line = "\x1b[46;97m[   95282,     4130] 3f: Indoor Unit 31     -> 84: Main WRC          : fc: Unknown              [0b]\x1b[0m";
+
+        let captured = parse_frame_line(line).unwrap().unwrap();
+        let RxFrame::Short(frame) = captured.frame else { panic!("expected a short frame") };
+        assert_eq!((frame.id.src, frame.id.dst, frame.id.cmd), (0x3f, 0x84, 0xfc));
+        assert_eq!(frame.data, [0x0b]);
+
+        // `dump --decode` output
+        let line = "[  199933,      131] 20: Indoor Unit 0      -> 84: Main WRC          : 52: IU Info 1 Response   [4b, 4d, 4f, f8, 81, 10, 00, 4e] setpoint=20C room=22C mode=Heat power=on byte3=f8";
+
+        let captured = parse_frame_line(line).unwrap().unwrap();
+        let RxFrame::Long(frame) = captured.frame else { panic!("expected a long frame") };
+        assert_eq!(frame.data, [0x4b, 0x4d, 0x4f, 0xf8, 0x81, 0x10, 0x00, 0x4e]);
+    }
+
+    #[test]
+    fn test_parse_non_frame_lines() {
+        assert!(parse_frame_line("").unwrap().is_none());
+        assert!(parse_frame_line("- physical main + sub wrc").unwrap().is_none());
+        assert!(parse_frame_line("[  199933,      131] garbage").is_err());
+    }
+
+    #[test]
+    fn test_parse_capture() {
+        let capture = Capture::parse(concat!(
+            "- physical main + sub wrc\n",
+            "\n",
+            "note: tracking frame address descriptions are all wrong.\n",
+            "\n",
+            "[   91151,     8595] 84: Main WRC           -> eb: Tracking Broadcast: fb: Unknown              [02, 00, 00, 00, 00, 00, 00, 00]\n",
+            "[   95282,     4130] 3f: Indoor Unit 31     -> 84: Main WRC          : fc: Unknown              [0b]\n",
+            "\n",
+            "\n",
+            "corrupted frame: [32, 84, 20]\n",
+            "IU powered off here\n",
+            "[  199801,      357] 84: Main WRC           -> 20: Indoor Unit 0     : 52: Info 1 Request       [00, 00, 00, 00, 00, 00, 00, 00]\n",
+            "\n",
+            "[  199933,      131] 20: Indoor Unit 0      -> 84: Main WRC          : 52: IU Info 1 Response   [4b, 4d, 4f, f8, 81, 10, 00, 4e]\n",
+            "\n",
+        )).unwrap();
+
+        assert_eq!(capture.frames.len(), 5);
+        assert_eq!(capture.frames[2].elapsed_ms, 95282);
+        assert!(matches!(&capture.frames[2].frame, RxFrame::Corrupted(data) if data == &[0x32, 0x84, 0x20]));
+
+        assert_eq!(capture.notes_before(0), ["- physical main + sub wrc", "note: tracking frame address descriptions are all wrong."]);
+        assert_eq!(capture.notes_before(3), ["IU powered off here"]);
+        assert!(capture.notes_before(1).is_empty());
+
+        assert_eq!(capture.markers.iter().filter(|marker| marker.annotation == Annotation::SectionBreak).count(), 2);
+        assert_eq!((0..5).map(|frame| capture.section_of(frame)).collect::<Vec<_>>(), [0, 0, 1, 1, 2]);
+    }
+}
@@ -1,16 +1,22 @@
 use futures::{AsyncRead, AsyncWrite, Stream, Sink, TryStream};
-use tokio::{net::TcpStream};
-use tokio_serial::{SerialStream, SerialPortBuilderExt};
+use tokio::{net::{TcpStream, TcpListener}};
+#[cfg(unix)]
+use tokio::net::{UnixStream, UnixListener};
+use tokio_serial::{SerialStream, SerialPortBuilderExt, DataBits, Parity, StopBits};
 use tokio_util::codec::Framed;
 use url::Url;
-use anyhow::{Result, Context, bail};
+use anyhow::{Result, Context, bail, anyhow};
 
-use crate::protocol::codec::{RxFrame, TxFrame, WrcBusProtocolCodec};
+use crate::{protocol::codec::{RxFrame, TxFrame, WrcBusProtocolCodec}, replay::ReplayPort, nng_port::NngPort};
 
 
 pub enum Port {
     Serial(SerialStream),
-    TcpRaw(TcpStream)
+    TcpRaw(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Nng(NngPort),
+    Replay(ReplayPort)
 }
 
 
@@ -32,19 +38,162 @@ where
 //impl<T> Foo for Framed<T, WrcBusProtocolCodec> {}
 
 
+/// RS-485 driver-enable handling for serial ports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rs485Mode {
+    /// Leave the port as-is (adapter handles direction switching itself).
+    Off,
+
+    /// Kernel RS-485 mode, RTS asserted while sending.
+    Rts,
+
+    /// Kernel RS-485 mode, RTS de-asserted while sending.
+    RtsInverted,
+}
+
+/// Serial line settings.
+/// 
+/// Parsed from the query parameters of a `serial://` URL, e.g.
+/// `serial:///dev/ttyUSB0?baud=9600&parity=even&stop=1&rs485=rts`.
+/// 
+/// Defaults to the COM2/WRC bus settings (2400 baud, 8 data bits, even parity, 1 stop bit).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SerialOptions {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub rs485: Rs485Mode,
+}
+
+impl Default for SerialOptions {
+    fn default() -> Self {
+        Self {
+            baud_rate: 2400,
+            data_bits: DataBits::Eight,
+            parity: Parity::Even,
+            stop_bits: StopBits::One,
+            rs485: Rs485Mode::Off
+        }
+    }
+}
+
+impl SerialOptions {
+    pub fn from_url(url: &Url) -> Result<Self> {
+        let mut options = Self::default();
+
+        for (key, value) in url.query_pairs() {
+            let invalid = || anyhow!("invalid value for serial option {key}: \"{value}\"");
+
+            match key.as_ref() {
+                "baud" => {
+                    options.baud_rate = value.parse().ok()
+                        .filter(|&baud| baud > 0)
+                        .ok_or_else(invalid)?;
+                },
+                "data" => {
+                    options.data_bits = match value.as_ref() {
+                        "5" => DataBits::Five,
+                        "6" => DataBits::Six,
+                        "7" => DataBits::Seven,
+                        "8" => DataBits::Eight,
+                        _ => return Err(invalid().context("expected one of 5, 6, 7 or 8"))
+                    };
+                },
+                "parity" => {
+                    options.parity = match value.as_ref() {
+                        "none" => Parity::None,
+                        "even" => Parity::Even,
+                        "odd" => Parity::Odd,
+                        _ => return Err(invalid().context("expected one of none, even or odd"))
+                    };
+                },
+                "stop" => {
+                    options.stop_bits = match value.as_ref() {
+                        "1" => StopBits::One,
+                        "2" => StopBits::Two,
+                        _ => return Err(invalid().context("expected one of 1 or 2"))
+                    };
+                },
+                "rs485" => {
+                    options.rs485 = match value.as_ref() {
+                        "off" => Rs485Mode::Off,
+                        "rts" => Rs485Mode::Rts,
+                        "rts-inverted" => Rs485Mode::RtsInverted,
+                        _ => return Err(invalid().context("expected one of off, rts or rts-inverted"))
+                    };
+                },
+                other => bail!("unknown serial option \"{other}\" in url: {url}")
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Configure kernel RS-485 mode (`TIOCSRS485`) on the serial port.
+#[cfg(target_os = "linux")]
+fn set_rs485_mode(port: &SerialStream, mode: Rs485Mode) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // from linux/serial.h (not exposed by libc)
+    const SER_RS485_ENABLED: u32 = 1 << 0;
+    const SER_RS485_RTS_ON_SEND: u32 = 1 << 1;
+    const SER_RS485_RTS_AFTER_SEND: u32 = 1 << 2;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SerialRs485 {
+        flags: u32,
+        delay_rts_before_send: u32,
+        delay_rts_after_send: u32,
+        padding: [u32; 5]
+    }
+
+    let flags = match mode {
+        Rs485Mode::Off => return Ok(()),
+        Rs485Mode::Rts => SER_RS485_ENABLED | SER_RS485_RTS_ON_SEND,
+        Rs485Mode::RtsInverted => SER_RS485_ENABLED | SER_RS485_RTS_AFTER_SEND,
+    };
+
+    let config = SerialRs485 { flags, ..Default::default() };
+
+    let res = unsafe { libc::ioctl(port.as_raw_fd(), libc::TIOCSRS485, &config as *const SerialRs485) };
+    if res < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_rs485_mode(_port: &SerialStream, mode: Rs485Mode) -> std::io::Result<()> {
+    match mode {
+        Rs485Mode::Off => Ok(()),
+        _ => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "kernel RS-485 mode is only supported on Linux"))
+    }
+}
+
+
 impl Port {
     pub async fn open(url: &Url) -> Result<Self> {
         match url.scheme() {
             "serial" => {
                 let path = url.path();
 
-                let port = tokio_serial::new(path, 2400)
-                    .stop_bits(tokio_serial::StopBits::One)
-                    .parity(tokio_serial::Parity::Even)
+                let options = SerialOptions::from_url(url)?;
+
+                let port = tokio_serial::new(path, options.baud_rate)
+                    .data_bits(options.data_bits)
+                    .stop_bits(options.stop_bits)
+                    .parity(options.parity)
                     .open_native_async()
                     .with_context(|| format!("failed to open serial port {path}"))
                     ?;
 
+                set_rs485_mode(&port, options.rs485)
+                    .with_context(|| format!("failed to configure RS-485 mode on serial port {path}"))?;
+
                 Ok(Self::Serial(port))
             },
             "tcp+raw" => {
@@ -61,6 +210,57 @@ impl Port {
 
                 Ok(Self::TcpRaw(stream))
             },
+            "tcp+raw+listen" => {
+                let host = url.host_str()
+                    .with_context(|| format!("tcp+raw+listen requires a host to be specified in the url: {url}"))?;
+
+                let port = url.port()
+                    .with_context(|| format!("tcp+raw+listen requires a port number to be specified in the url: {url}"))?;
+
+                let listener = TcpListener::bind((host, port)).await
+                    .with_context(|| format!("failed to listen on: {url}"))?;
+
+                println!("{url}: waiting for connection...");
+
+                let (stream, addr) = listener.accept().await
+                    .with_context(|| format!("failed to accept connection on: {url}"))?;
+
+                println!("{url}: accepted connection from {addr}");
+
+                stream.set_nodelay(true)?;
+
+                Ok(Self::TcpRaw(stream))
+            },
+            #[cfg(unix)]
+            "unix" => {
+                let path = url.path();
+
+                let stream = UnixStream::connect(path).await
+                    .with_context(|| format!("failed to connect to unix socket {path}"))?;
+
+                Ok(Self::Unix(stream))
+            },
+            #[cfg(unix)]
+            "unix+listen" => {
+                let path = url.path();
+
+                let listener = bind_unix_listener(path)?;
+
+                println!("{url}: waiting for connection...");
+
+                let (stream, _) = listener.accept().await
+                    .with_context(|| format!("failed to accept connection on unix socket {path}"))?;
+
+                println!("{url}: accepted connection");
+
+                Ok(Self::Unix(stream))
+            },
+            "nng+bus" | "nng+bus+listen" => {
+                Ok(Self::Nng(NngPort::open(url)?))
+            },
+            "replay" => {
+                Ok(Self::Replay(ReplayPort::open(url).await?))
+            },
             other => {
                 bail!("url scheme {other} not supported");
             }
@@ -76,7 +276,107 @@ impl Port {
             },
             Port::TcpRaw(stream) => {
                 Box::new(Framed::new(stream, WrcBusProtocolCodec::new()))
+            },
+            #[cfg(unix)]
+            Port::Unix(stream) => {
+                Box::new(Framed::new(stream, WrcBusProtocolCodec::new()))
+            },
+            Port::Nng(port) => {
+                Box::new(port)
+            },
+            Port::Replay(port) => {
+                Box::new(port)
             }
         }
     }
 }
+
+
+/// Bind a `UnixListener` to `path`, replacing a stale socket left behind by a previous process.
+#[cfg(unix)]
+pub fn bind_unix_listener(path: &str) -> Result<UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{path} exists and is not a unix socket");
+        }
+
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale unix socket {path}"))?;
+    }
+
+    UnixListener::bind(path)
+        .with_context(|| format!("failed to listen on unix socket {path}"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_options_default() {
+        let url = Url::parse("serial:///dev/ttyUSB0").unwrap();
+
+        assert_eq!(SerialOptions::from_url(&url).unwrap(), SerialOptions::default());
+    }
+
+    #[test]
+    fn test_serial_options_from_url() {
+        let url = Url::parse("serial:///dev/ttyUSB0?baud=9600&parity=odd&stop=2&data=7&rs485=rts").unwrap();
+
+        let options = SerialOptions::from_url(&url).unwrap();
+
+        assert_eq!(options, SerialOptions {
+            baud_rate: 9600,
+            data_bits: DataBits::Seven,
+            parity: Parity::Odd,
+            stop_bits: StopBits::Two,
+            rs485: Rs485Mode::Rts
+        });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_listen() {
+        use futures::TryStreamExt;
+        use tokio::io::AsyncWriteExt;
+
+        let path = std::env::temp_dir().join(format!("samsunghvac2mqtt-test-{}.sock", std::process::id()));
+        let url = Url::parse(&format!("unix+listen://{}", path.display())).unwrap();
+
+        let listen = tokio::spawn(async move {
+            Port::open(&url).await.unwrap().framed().try_next().await.unwrap()
+        });
+
+        // wait for the listener to bind
+        while !path.exists() {
+            tokio::task::yield_now().await;
+        }
+
+        let mut client = UnixStream::connect(&path).await.unwrap();
+        client.write_all(&[0x32, 0x84, 0xeb, 0xf9, 0x00, 0x96, 0x34]).await.unwrap();
+
+        let frame = listen.await.unwrap().unwrap();
+        assert_eq!(frame.id().unwrap().cmd, 0xf9);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_serial_options_invalid() {
+        for url in [
+            "serial:///dev/ttyUSB0?baud=fast",
+            "serial:///dev/ttyUSB0?baud=0",
+            "serial:///dev/ttyUSB0?parity=mark",
+            "serial:///dev/ttyUSB0?stop=3",
+            "serial:///dev/ttyUSB0?rs485=dtr",
+            "serial:///dev/ttyUSB0?speed=9600",
+        ] {
+            let url = Url::parse(url).unwrap();
+
+            assert!(SerialOptions::from_url(&url).is_err(), "{url} should be rejected");
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow, bail};
+use tokio::sync::{mpsc, broadcast, oneshot};
+
+use crate::{cache::BusCache, subwrc::SubWrcEvent, protocol::iu::{IndoorUnitState, IndoorUnitMode, IndoorUnitFanSpeed, IndoorUnitCapabilities, IndoorUnitField}};
+
+
+/// Setpoint range accepted by the IUs, in °C.
+pub const SETPOINT_RANGE: std::ops::RangeInclusive<u8> = 16..=30;
+
+/// The IUs a control request applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    IndoorUnit(u8),
+
+    /// Every IU on the bus (that supports the setting).
+    All
+}
+
+impl From<u8> for Target {
+    fn from(address: u8) -> Self {
+        Target::IndoorUnit(address)
+    }
+}
+
+/// Optional IU features that can be switched on and off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndoorUnitOption {
+    SPlasmaIon,
+    Humidifier
+}
+
+/// A single IU setting to change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Power(bool),
+    Mode(IndoorUnitMode),
+    Setpoint(u8),
+    FanSpeed(IndoorUnitFanSpeed),
+    Option(IndoorUnitOption, bool)
+}
+
+impl Setting {
+    pub fn check(&self) -> Result<()> {
+        if let Setting::Setpoint(temp) = self {
+            if !SETPOINT_RANGE.contains(temp) {
+                bail!("setpoint {temp} is out of range ({}-{})", SETPOINT_RANGE.start(), SETPOINT_RANGE.end());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Does an IU with `capabilities` support this setting?
+    pub fn supported_by(&self, capabilities: &IndoorUnitCapabilities) -> bool {
+        match self {
+            // mode and fan speed support isn't detected (see [IndoorUnitCapabilities])
+            Setting::Power(_) | Setting::Setpoint(_) | Setting::Mode(_) | Setting::FanSpeed(_) => true,
+            Setting::Option(IndoorUnitOption::SPlasmaIon, _) => capabilities.supports(IndoorUnitField::SPlasmaIon),
+            Setting::Option(IndoorUnitOption::Humidifier, _) => capabilities.supports(IndoorUnitField::Humidifier),
+        }
+    }
+
+    pub fn apply(&self, state: &mut IndoorUnitState) {
+        match *self {
+            Setting::Power(power) => state.power = power,
+            Setting::Mode(mode) => state.mode = mode,
+            Setting::Setpoint(temp) => state.setpoint_temp = temp,
+            Setting::FanSpeed(fan_speed) => state.fan_speed = fan_speed,
+            Setting::Option(IndoorUnitOption::SPlasmaIon, enabled) => state.s_plasma_ion = enabled,
+            Setting::Option(IndoorUnitOption::Humidifier, enabled) => state.humidifier = enabled,
+        }
+    }
+
+    /// Is the setting reflected in `state`?
+    pub fn is_applied(&self, state: &IndoorUnitState) -> bool {
+        match *self {
+            Setting::Power(power) => state.power == power,
+            Setting::Mode(mode) => state.mode == mode,
+            Setting::Setpoint(temp) => state.setpoint_temp == temp,
+            Setting::FanSpeed(fan_speed) => state.fan_speed == fan_speed,
+            Setting::Option(IndoorUnitOption::SPlasmaIon, enabled) => state.s_plasma_ion == enabled,
+            Setting::Option(IndoorUnitOption::Humidifier, enabled) => state.humidifier == enabled,
+        }
+    }
+
+    /// Do both settings change the same thing?
+    pub fn same_kind(&self, other: &Setting) -> bool {
+        match (self, other) {
+            (Setting::Option(a, _), Setting::Option(b, _)) => a == b,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other)
+        }
+    }
+}
+
+/// A request from a [HvacController] to the sub WRC task.
+pub(crate) struct ControlRequest {
+    pub target: Target,
+    pub setting: Setting,
+    pub done: oneshot::Sender<Result<()>>
+}
+
+/// Handle for controlling the IUs via the sub WRC task.
+///
+/// Each change is merged with the last known state of the IU and sent on the next handover
+/// from the main WRC. It completes once the IU has acked the change (`0x50`)
+/// and its next `0x52` reply confirms it.
+#[derive(Clone)]
+pub struct HvacController {
+    requests: mpsc::Sender<ControlRequest>,
+    events: broadcast::Sender<SubWrcEvent>,
+    restored: Arc<BusCache>
+}
+
+impl HvacController {
+    pub(crate) fn new(requests: mpsc::Sender<ControlRequest>, events: broadcast::Sender<SubWrcEvent>, restored: Arc<BusCache>) -> Self {
+        Self { requests, events, restored }
+    }
+
+    /// Topology and IU states restored from the state file when the sub WRC task was created
+    /// (empty if there wasn't one).
+    ///
+    /// The restored IU states are stale until a [SubWrcEvent::Refreshed] (or [SubWrcEvent::CacheInvalidated]).
+    pub fn restored(&self) -> &BusCache {
+        &self.restored
+    }
+
+    /// Subscribe to everything the sub WRC learns about the bus.
+    pub fn subscribe(&self) -> broadcast::Receiver<SubWrcEvent> {
+        self.events.subscribe()
+    }
+
+    /// Change a setting of one or all IUs.
+    pub async fn set(&self, target: impl Into<Target>, setting: Setting) -> Result<()> {
+        setting.check()?;
+
+        let (done, result) = oneshot::channel();
+
+        self.requests.send(ControlRequest { target: target.into(), setting, done }).await
+            .map_err(|_| anyhow!("sub WRC task has stopped"))?;
+
+        result.await.map_err(|_| anyhow!("sub WRC task has stopped"))?
+    }
+
+    pub async fn set_power(&self, target: impl Into<Target>, power: bool) -> Result<()> {
+        self.set(target, Setting::Power(power)).await
+    }
+
+    pub async fn set_mode(&self, target: impl Into<Target>, mode: IndoorUnitMode) -> Result<()> {
+        self.set(target, Setting::Mode(mode)).await
+    }
+
+    /// Set the setpoint temperature (°C).
+    pub async fn set_setpoint(&self, target: impl Into<Target>, temp: u8) -> Result<()> {
+        self.set(target, Setting::Setpoint(temp)).await
+    }
+
+    pub async fn set_fan_speed(&self, target: impl Into<Target>, fan_speed: IndoorUnitFanSpeed) -> Result<()> {
+        self.set(target, Setting::FanSpeed(fan_speed)).await
+    }
+
+    pub async fn set_option(&self, target: impl Into<Target>, option: IndoorUnitOption, enabled: bool) -> Result<()> {
+        self.set(target, Setting::Option(option, enabled)).await
+    }
+}
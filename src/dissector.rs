@@ -0,0 +1,271 @@
+use std::{fmt::Write, collections::BTreeSet};
+
+use crate::protocol::layout::{CommandLayout, FieldKind, FieldLayout};
+
+
+/// Protocol name (and field prefix) of the dissector.
+const PROTOCOL: &str = "samsunghvac";
+
+/// A field of a command, positioned within the data bytes it spans.
+struct LuaField {
+    /// Field abbreviation, e.g. `samsunghvac.commanda0.setpoint_temp`.
+    abbr: String,
+    name: String,
+
+    /// First data byte and number of bytes spanned.
+    offset: usize,
+    length: usize,
+
+    mask: u32,
+    shift: usize,
+
+    kind: &'static str,
+    values: Option<String>,
+    unknown: bool
+}
+
+impl LuaField {
+    fn new(command: &CommandLayout, field: &FieldLayout) -> Self {
+        let (start, end) = (*field.bits.start(), *field.bits.end());
+
+        let offset = start / 8;
+        let length = end / 8 - offset + 1;
+        let shift = length * 8 - 1 - (end - offset * 8);
+        let mask = (((1_u64 << (end - start + 1)) - 1) << shift) as u32;
+
+        let name = field.name.replace(['[', ']'], "_").trim_end_matches('_').to_string();
+
+        let (kind, values) = match &field.kind {
+            FieldKind::Bool => ("bool", None),
+            FieldKind::Uint => ("uint", None),
+            FieldKind::Temperature => ("temperature", None),
+            FieldKind::PrecisionTemperature => ("precision_temperature", None),
+            FieldKind::Enum(variants) => {
+                let values: Vec<_> = variants.iter().map(|(raw, name)| format!("[{raw}] = \"{name}\"")).collect();
+                ("enum", Some(format!("{{ {} }}", values.join(", "))))
+            }
+        };
+
+        Self {
+            abbr: format!("{PROTOCOL}.{}.{name}", command.name.to_lowercase()),
+            name: field.name.clone(),
+            offset,
+            length,
+            mask,
+            shift,
+            kind,
+            values,
+            unknown: field.unknown
+        }
+    }
+
+    /// Bits of a data byte not covered by any of the command's fields.
+    fn uncovered(command: &CommandLayout, byte: usize, mask: u8) -> Self {
+        Self {
+            abbr: format!("{PROTOCOL}.{}.byte{byte}", command.name.to_lowercase()),
+            name: format!("byte{byte} (unmapped bits)"),
+            offset: byte,
+            length: 1,
+            mask: mask as u32,
+            shift: 0,
+            kind: "uint",
+            values: None,
+            unknown: true
+        }
+    }
+
+    fn definition(&self) -> String {
+        let width = self.length * 8;
+        let Self { abbr, name, mask, .. } = self;
+
+        match (self.kind, &self.values) {
+            ("bool", _) => format!("ProtoField.bool(\"{abbr}\", \"{name}\", {width}, nil, 0x{mask:x})"),
+            (_, Some(values)) => format!("ProtoField.uint{width}(\"{abbr}\", \"{name}\", base.DEC, {values}, 0x{mask:x})"),
+            _ if self.unknown => format!("ProtoField.uint{width}(\"{abbr}\", \"{name}\", base.HEX, nil, 0x{mask:x})"),
+            _ => format!("ProtoField.uint{width}(\"{abbr}\", \"{name}\", base.DEC, nil, 0x{mask:x})"),
+        }
+    }
+}
+
+/// Generate a Lua Wireshark dissector for the given command layouts
+/// (see [crate::protocol::layout::commands]).
+///
+/// Frames are expected as whole packets (start marker to end marker) with the `USER0`
+/// link-layer type, as written by `dump --format pcapng`.
+pub fn lua_dissector(commands: &[CommandLayout]) -> String {
+    let commands: Vec<(&CommandLayout, Vec<LuaField>)> = commands.iter()
+        .map(|command| {
+            let mut fields: Vec<_> = command.fields.iter().map(|field| LuaField::new(command, field)).collect();
+
+            fields.extend(command.uncovered_bits().into_iter().enumerate()
+                .filter(|(_, mask)| *mask != 0)
+                .map(|(byte, mask)| LuaField::uncovered(command, byte, mask)));
+
+            (command, fields)
+        })
+        .collect();
+
+    let mut lua = String::new();
+
+    writeln!(lua, "-- Samsung HVAC WRC bus dissector for Wireshark.").unwrap();
+    writeln!(lua, "--").unwrap();
+    writeln!(lua, "-- Generated by the `dissector` tool from the command definitions in `protocol::commands`; don't edit.").unwrap();
+    writeln!(lua, "-- Reads frames with the USER0 link-layer type, e.g. from `dump --format pcapng`.").unwrap();
+    writeln!(lua).unwrap();
+    writeln!(lua, "local proto = Proto(\"{PROTOCOL}\", \"Samsung HVAC WRC bus\")").unwrap();
+    writeln!(lua).unwrap();
+    writeln!(lua, "local fields = {{").unwrap();
+    writeln!(lua, "    src = ProtoField.uint8(\"{PROTOCOL}.src\", \"Source\", base.HEX),").unwrap();
+    writeln!(lua, "    dst = ProtoField.uint8(\"{PROTOCOL}.dst\", \"Destination\", base.HEX),").unwrap();
+    writeln!(lua, "    cmd = ProtoField.uint8(\"{PROTOCOL}.cmd\", \"Command\", base.HEX),").unwrap();
+    writeln!(lua, "    data = ProtoField.bytes(\"{PROTOCOL}.data\", \"Data\"),").unwrap();
+    writeln!(lua, "    checksum = ProtoField.uint8(\"{PROTOCOL}.checksum\", \"Checksum\", base.HEX),").unwrap();
+
+    // the same struct can be used for several commands (e.g. both directions of `0x64`)
+    let mut defined = BTreeSet::new();
+    for (_, fields) in &commands {
+        for field in fields {
+            if defined.insert(&field.abbr) {
+                writeln!(lua, "    [\"{}\"] = {},", field.abbr, field.definition()).unwrap();
+            }
+        }
+    }
+
+    writeln!(lua, "}}").unwrap();
+    writeln!(lua).unwrap();
+    writeln!(lua, "local field_list = {{}}").unwrap();
+    writeln!(lua, "for _, field in pairs(fields) do table.insert(field_list, field) end").unwrap();
+    writeln!(lua, "proto.fields = field_list").unwrap();
+    writeln!(lua).unwrap();
+    writeln!(lua, "local unknown_bits = ProtoExpert.new(\"{PROTOCOL}.unknown_bits\", \"Unknown bits set\", expert.group.UNDECODED, expert.severity.NOTE)").unwrap();
+    writeln!(lua, "local bad_checksum = ProtoExpert.new(\"{PROTOCOL}.bad_checksum\", \"Invalid checksum\", expert.group.CHECKSUM, expert.severity.ERROR)").unwrap();
+    writeln!(lua, "proto.experts = {{ unknown_bits, bad_checksum }}").unwrap();
+    writeln!(lua).unwrap();
+    writeln!(lua, "-- the first matching command applies").unwrap();
+    writeln!(lua, "-- fields: {{ field, offset, length, mask, shift, kind, unknown }}").unwrap();
+    writeln!(lua, "local commands = {{").unwrap();
+
+    for (command, fields) in &commands {
+        writeln!(lua, "    {{ name = \"{}\", cmd = 0x{:02x}, src = {{ 0x{:02x}, 0x{:02x} }}, dst = {{ 0x{:02x}, 0x{:02x} }}, size = {}, fields = {{",
+            command.name, command.cmd,
+            command.src.start(), command.src.end(),
+            command.dst.start(), command.dst.end(),
+            command.size).unwrap();
+
+        for field in fields {
+            writeln!(lua, "        {{ fields[\"{}\"], {}, {}, 0x{:x}, {}, \"{}\", {} }},",
+                field.abbr, field.offset, field.length, field.mask, field.shift, field.kind, field.unknown).unwrap();
+        }
+
+        writeln!(lua, "    }} }},").unwrap();
+    }
+
+    writeln!(lua, "}}").unwrap();
+    lua.push_str(DISSECTOR);
+
+    lua
+}
+
+/// The hand-written part of the dissector.
+const DISSECTOR: &str = r#"
+local function in_range(value, range)
+    return value >= range[1] and value <= range[2]
+end
+
+local function find_command(src, dst, cmd, size)
+    for _, command in ipairs(commands) do
+        if command.cmd == cmd and command.size == size and in_range(src, command.src) and in_range(dst, command.dst) then
+            return command
+        end
+    end
+end
+
+function proto.dissector(buffer, pinfo, tree)
+    local length = buffer:len()
+    if length < 7 or buffer(0, 1):uint() ~= 0x32 or buffer(length - 1, 1):uint() ~= 0x34 then
+        return 0
+    end
+
+    pinfo.cols.protocol = "WRC"
+
+    local src, dst, cmd = buffer(1, 1):uint(), buffer(2, 1):uint(), buffer(3, 1):uint()
+    local size = length - 6
+    local data = buffer(4, size)
+
+    local subtree = tree:add(proto, buffer())
+    subtree:add(fields.src, buffer(1, 1))
+    subtree:add(fields.dst, buffer(2, 1))
+    subtree:add(fields.cmd, buffer(3, 1))
+
+    local command = find_command(src, dst, cmd, size)
+    local datatree = subtree:add(fields.data, data)
+
+    if command == nil then
+        pinfo.cols.info = string.format("%02x -> %02x: %02x", src, dst, cmd)
+    else
+        pinfo.cols.info = string.format("%02x -> %02x: %02x %s", src, dst, cmd, command.name)
+        datatree:set_text(command.name)
+
+        for _, f in ipairs(command.fields) do
+            local field, offset, len, mask, shift, kind, unknown = f[1], f[2], f[3], f[4], f[5], f[6], f[7]
+            local range = buffer(4 + offset, len)
+            local item = datatree:add(field, range)
+            local value = bit.rshift(bit.band(range:uint(), mask), shift)
+
+            if kind == "temperature" then
+                if bit.band(value, 0x80) == 0 then
+                    item:append_text(string.format(" (%d C)", bit.band(value, 0x7f) - 55))
+                else
+                    item:append_text(string.format(" (%d F)", bit.band(value, 0x7f)))
+                end
+            elseif kind == "precision_temperature" then
+                item:append_text(string.format(" (%.1f C)", (value - 553) / 10))
+            end
+
+            if unknown and value ~= 0 then
+                item:add_proto_expert_info(unknown_bits)
+            end
+        end
+    end
+
+    local checksum = 0
+    for i = 1, length - 3 do
+        checksum = bit.bxor(checksum, buffer(i, 1):uint())
+    end
+
+    local item = subtree:add(fields.checksum, buffer(length - 2, 1))
+    if checksum ~= buffer(length - 2, 1):uint() then
+        item:add_proto_expert_info(bad_checksum)
+    end
+
+    return length
+end
+
+DissectorTable.get("wtap_encap"):add(wtap.USER0, proto)
+"#;
+
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::layout::commands;
+
+    use super::*;
+
+    #[test]
+    fn test_lua_dissector() {
+        let lua = lua_dissector(&commands());
+
+        assert!(lua.contains(r#"["samsunghvac.commanda0.setpoint_temp"] = ProtoField.uint8("samsunghvac.commanda0.setpoint_temp", "setpoint_temp", base.DEC, nil, 0x1f),"#));
+        assert!(lua.contains(r#"["samsunghvac.commanda0.mode"] = ProtoField.uint8("samsunghvac.commanda0.mode", "mode", base.DEC, { [0] = "Auto", [1] = "Cool", [2] = "Dry", [3] = "Fan", [4] = "Heat" }, 0x7),"#));
+        assert!(lua.contains(r#"["samsunghvac.commanda0.power"] = ProtoField.bool("samsunghvac.commanda0.power", "power", 8, nil, 0x20),"#));
+        assert!(lua.contains(r#"["samsunghvac.command64request.wrc_temperature"] = ProtoField.uint16("samsunghvac.command64request.wrc_temperature", "wrc_temperature", base.DEC, nil, 0xffff),"#));
+        assert!(lua.contains(r#"["samsunghvac.command55indoorunitresponse.unknown_0"] = ProtoField.uint8("samsunghvac.command55indoorunitresponse.unknown_0", "unknown[0]", base.HEX, nil, 0xff),"#));
+
+        // unmapped bits 24 and 28
+        assert!(lua.contains(r#"{ fields["samsunghvac.commanda0.byte3"], 3, 1, 0x88, 0, "uint", true },"#));
+        assert!(lua.contains(r#"{ name = "CommandA0", cmd = 0x50, src = { 0x20, 0x3f }, dst = { 0x84, 0x85 }, size = 8, fields = {"#));
+
+        // each field is only defined once
+        assert_eq!(lua.matches(r#"["samsunghvac.command64request.iu_temperature"] ="#).count(), 1);
+    }
+}
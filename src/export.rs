@@ -0,0 +1,373 @@
+use std::{io::Write, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+
+use crate::protocol::{codec::{RxFrame, FrameId}, addresses::*, decode::{decode, Field}};
+
+
+/// Link-layer type of exported pcapng captures (`LINKTYPE_USER0`).
+///
+/// Wireshark shows these as raw data unless a dissector is mapped to `DLT_USER0`.
+pub const PCAPNG_LINKTYPE: u16 = 147;
+
+/// A received frame, with the timing information recorded by the `dump` tool.
+pub struct ExportedFrame<'a> {
+    /// Time the frame was received.
+    pub timestamp: SystemTime,
+
+    /// Milliseconds since the start of the capture.
+    pub elapsed_ms: u128,
+
+    /// Milliseconds since the previous frame.
+    pub delta_ms: u128,
+
+    pub frame: &'a RxFrame,
+
+    /// Section of the capture (see [crate::capture::Capture]), always `0` for live frames.
+    pub section: usize,
+
+    /// Notes from the capture directly before the frame.
+    pub notes: Vec<&'a str>
+}
+
+/// Whether a frame was sent by a WRC or is a reply to one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Request,
+    Reply
+}
+
+impl Direction {
+    pub fn of(id: FrameId) -> Option<Self> {
+        match (id.src, id.dst) {
+            (MAIN_WRC, _) => Some(Direction::Request),
+            (_, MAIN_WRC) => Some(Direction::Reply),
+            (SUB_WRC, _) => Some(Direction::Request),
+            (_, SUB_WRC) => Some(Direction::Reply),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Request => "request",
+            Direction::Reply => "reply",
+        }
+    }
+}
+
+impl ExportedFrame<'_> {
+    fn timestamp_us(&self) -> u64 {
+        self.timestamp.duration_since(UNIX_EPOCH).map(|t| t.as_micros() as u64).unwrap_or_default()
+    }
+
+    fn direction(&self) -> Option<Direction> {
+        Direction::of(self.frame.id()?)
+    }
+
+    /// Corrupted frames are the only ones that fail their checksum.
+    fn checksum(&self) -> &'static str {
+        match self.frame {
+            RxFrame::Corrupted(_) => "invalid",
+            _ => "ok"
+        }
+    }
+
+    /// Frame data (without the header/checksum), in hex.
+    fn data(&self) -> String {
+        let data = match self.frame {
+            RxFrame::Long(frame) => &frame.data[..],
+            RxFrame::Short(frame) => &frame.data[..],
+            RxFrame::Corrupted(data) => &data[..],
+        };
+
+        data.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn fields(&self) -> Vec<Field> {
+        decode(self.frame).unwrap_or_default()
+    }
+}
+
+/// Writes captured frames in a machine-readable format.
+pub trait FrameExporter {
+    fn export(&mut self, frame: &ExportedFrame) -> Result<()>;
+}
+
+/// One JSON object per line, e.g.
+///
+/// `{"timestamp_us":…,"elapsed_ms":…,"delta_ms":…,"direction":"reply","src":"20","dst":"84","cmd":"52","data":"4a4c4ef82210004e","checksum":"ok","fields":{"setpoint":"19C",…},"unknown_fields":["byte3"],"section":0,"notes":[]}`
+///
+/// `src`, `dst` and `cmd` are `null` for corrupted frames, as is `direction` when it isn't known.
+pub struct JsonlExporter<W: Write> {
+    output: W
+}
+
+impl<W: Write> JsonlExporter<W> {
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+}
+
+impl<W: Write> FrameExporter for JsonlExporter<W> {
+    fn export(&mut self, frame: &ExportedFrame) -> Result<()> {
+        let id = frame.frame.id();
+        let fields = frame.fields();
+
+        let record = json!({
+            "timestamp_us": frame.timestamp_us(),
+            "elapsed_ms": frame.elapsed_ms as u64,
+            "delta_ms": frame.delta_ms as u64,
+            "direction": frame.direction().map(|direction| direction.name()),
+            "src": id.map(|id| format!("{:02x}", id.src)),
+            "dst": id.map(|id| format!("{:02x}", id.dst)),
+            "cmd": id.map(|id| format!("{:02x}", id.cmd)),
+            "data": frame.data(),
+            "checksum": frame.checksum(),
+            "fields": fields.iter()
+                .map(|field| (field.name.clone(), Value::String(field.value.clone())))
+                .collect::<Map<_, _>>(),
+            "unknown_fields": fields.iter()
+                .filter(|field| field.unknown)
+                .map(|field| field.name.clone())
+                .collect::<Vec<_>>(),
+            "section": frame.section,
+            "notes": frame.notes,
+        });
+
+        writeln!(self.output, "{record}")?;
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+/// CSV with a header row. Decoded fields are space separated `name=value` pairs,
+/// with the names of unknown fields repeated in `unknown_fields`. Notes are separated by newlines.
+pub struct CsvExporter<W: Write> {
+    output: W,
+    header_written: bool
+}
+
+impl<W: Write> CsvExporter<W> {
+    pub const HEADER: &'static str = "timestamp_us,elapsed_ms,delta_ms,direction,src,dst,cmd,data,checksum,fields,unknown_fields,section,notes";
+
+    pub fn new(output: W) -> Self {
+        Self { output, header_written: false }
+    }
+}
+
+/// Quote a CSV value if required.
+fn csv_value(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl<W: Write> FrameExporter for CsvExporter<W> {
+    fn export(&mut self, frame: &ExportedFrame) -> Result<()> {
+        if !self.header_written {
+            writeln!(self.output, "{}", Self::HEADER)?;
+            self.header_written = true;
+        }
+
+        let id = frame.frame.id();
+        let hex = |value: Option<u8>| value.map(|value| format!("{value:02x}")).unwrap_or_default();
+        let fields = frame.fields();
+
+        let row = [
+            frame.timestamp_us().to_string(),
+            frame.elapsed_ms.to_string(),
+            frame.delta_ms.to_string(),
+            frame.direction().map(|direction| direction.name()).unwrap_or_default().to_string(),
+            hex(id.map(|id| id.src)),
+            hex(id.map(|id| id.dst)),
+            hex(id.map(|id| id.cmd)),
+            frame.data(),
+            frame.checksum().to_string(),
+            fields.iter().map(Field::to_string).collect::<Vec<_>>().join(" "),
+            fields.iter().filter(|field| field.unknown).map(|field| field.name.as_str()).collect::<Vec<_>>().join(" "),
+            frame.section.to_string(),
+            frame.notes.join("\n"),
+        ];
+
+        let row: Vec<_> = row.iter().map(|value| csv_value(value)).collect();
+        writeln!(self.output, "{}", row.join(","))?;
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+/// pcapng capture of the raw frames (as sent on the wire), with [PCAPNG_LINKTYPE] as the link-layer type.
+///
+/// Each packet has a comment with its direction and decoded fields, and one for each note before it.
+pub struct PcapngExporter<W: Write> {
+    output: W,
+    header_written: bool
+}
+
+impl<W: Write> PcapngExporter<W> {
+    const SECTION_HEADER_BLOCK: u32 = 0x0a0d0d0a;
+    const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x00000001;
+    const ENHANCED_PACKET_BLOCK: u32 = 0x00000006;
+
+    const OPT_END: u16 = 0;
+    const OPT_COMMENT: u16 = 1;
+    const IF_NAME: u16 = 2;
+
+    pub fn new(output: W) -> Self {
+        Self { output, header_written: false }
+    }
+
+    fn option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+        body.extend(code.to_le_bytes());
+        body.extend((value.len() as u16).to_le_bytes());
+        body.extend(value);
+        body.resize(body.len().next_multiple_of(4), 0);
+    }
+
+    /// Write a block, padding the body to 32 bits.
+    fn block(&mut self, block_type: u32, mut body: Vec<u8>) -> Result<()> {
+        body.resize(body.len().next_multiple_of(4), 0);
+
+        let length = (body.len() + 12) as u32;
+
+        self.output.write_all(&block_type.to_le_bytes())?;
+        self.output.write_all(&length.to_le_bytes())?;
+        self.output.write_all(&body)?;
+        self.output.write_all(&length.to_le_bytes())?;
+
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let mut section = Vec::new();
+        section.extend(0x1a2b3c4d_u32.to_le_bytes()); // byte order magic
+        section.extend(1_u16.to_le_bytes()); // version 1.0
+        section.extend(0_u16.to_le_bytes());
+        section.extend((-1_i64).to_le_bytes()); // section length not specified
+        self.block(Self::SECTION_HEADER_BLOCK, section)?;
+
+        // timestamps are in microseconds (the default resolution)
+        let mut interface = Vec::new();
+        interface.extend(PCAPNG_LINKTYPE.to_le_bytes());
+        interface.extend(0_u16.to_le_bytes()); // reserved
+        interface.extend(0_u32.to_le_bytes()); // no snap length
+        Self::option(&mut interface, Self::IF_NAME, b"wrc-bus");
+        Self::option(&mut interface, Self::OPT_END, &[]);
+        self.block(Self::INTERFACE_DESCRIPTION_BLOCK, interface)
+    }
+}
+
+impl<W: Write> FrameExporter for PcapngExporter<W> {
+    fn export(&mut self, frame: &ExportedFrame) -> Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+            self.header_written = true;
+        }
+
+        let timestamp = frame.timestamp_us();
+        let bytes = frame.frame.to_bytes();
+
+        let mut packet = Vec::new();
+        packet.extend(0_u32.to_le_bytes()); // interface
+        packet.extend(((timestamp >> 32) as u32).to_le_bytes());
+        packet.extend((timestamp as u32).to_le_bytes());
+        packet.extend((bytes.len() as u32).to_le_bytes()); // captured length
+        packet.extend((bytes.len() as u32).to_le_bytes()); // original length
+        packet.extend(&bytes);
+        packet.resize(packet.len().next_multiple_of(4), 0);
+
+        let mut comment: Vec<String> = frame.direction().map(|direction| direction.name().to_string()).into_iter().collect();
+        if matches!(frame.frame, RxFrame::Corrupted(_)) {
+            comment.push("corrupted".to_string());
+        }
+        comment.extend(frame.fields().iter().map(Field::to_string));
+
+        for note in &frame.notes {
+            Self::option(&mut packet, Self::OPT_COMMENT, note.as_bytes());
+        }
+        if !comment.is_empty() {
+            Self::option(&mut packet, Self::OPT_COMMENT, comment.join(" ").as_bytes());
+        }
+        if !comment.is_empty() || !frame.notes.is_empty() {
+            Self::option(&mut packet, Self::OPT_END, &[]);
+        }
+
+        self.block(Self::ENHANCED_PACKET_BLOCK, packet)?;
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::protocol::codec::LongFrame;
+
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let frame = RxFrame::Long(LongFrame::new(0x20, MAIN_WRC, 0x52, [0x4a, 0x4c, 0x4e, 0xf8, 0x22, 0x10, 0x00, 0x4e]));
+        let exported = ExportedFrame {
+            timestamp: UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456),
+            elapsed_ms: 1500,
+            delta_ms: 20,
+            frame: &frame,
+            section: 1,
+            notes: vec!["pressed swing"]
+        };
+
+        let mut jsonl = JsonlExporter::new(Vec::new());
+        jsonl.export(&exported).unwrap();
+
+        let record: Value = serde_json::from_slice(&jsonl.output).unwrap();
+        assert_eq!(record["timestamp_us"], 1_700_000_000_123_456_u64);
+        assert_eq!(record["direction"], "reply");
+        assert_eq!((&record["src"], &record["dst"], &record["cmd"]), (&json!("20"), &json!("84"), &json!("52")));
+        assert_eq!(record["data"], "4a4c4ef82210004e");
+        assert_eq!(record["fields"]["setpoint"], "19C");
+        assert_eq!(record["unknown_fields"], json!(["bit34", "byte3"]));
+        assert_eq!((&record["section"], &record["notes"]), (&json!(1), &json!(["pressed swing"])));
+
+        let mut csv = CsvExporter::new(Vec::new());
+        csv.export(&exported).unwrap();
+        csv.export(&exported).unwrap();
+
+        let csv = String::from_utf8(csv.output).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CsvExporter::<Vec<u8>>::HEADER);
+        assert!(lines[1].starts_with("1700000000123456,1500,20,reply,20,84,52,4a4c4ef82210004e,ok,setpoint=19C room=21C"));
+        assert!(lines[1].ends_with(",bit34 byte3,1,pressed swing"));
+
+        let mut pcapng = PcapngExporter::new(Vec::new());
+        pcapng.export(&exported).unwrap();
+
+        let output = pcapng.output;
+        let u32_at = |offset: usize| u32::from_le_bytes(output[offset..offset + 4].try_into().unwrap());
+
+        // section header, interface description, then the packet
+        assert_eq!(u32_at(0), 0x0a0d0d0a);
+        let interface = u32_at(4) as usize;
+        assert_eq!(u32_at(interface), 1);
+        assert_eq!(u16::from_le_bytes([output[interface + 8], output[interface + 9]]), PCAPNG_LINKTYPE);
+
+        let packet = interface + u32_at(interface + 4) as usize;
+        assert_eq!(u32_at(packet), 6);
+        assert_eq!(u32_at(packet + 20), 14);
+        assert_eq!(&output[packet + 28..packet + 42], &frame.to_bytes()[..]);
+
+        let length = u32_at(packet + 4) as usize;
+        assert_eq!(packet + length, output.len());
+        assert_eq!(u32_at(output.len() - 4) as usize, length);
+    }
+}
@@ -0,0 +1,159 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use anyhow::{Result, Context, bail};
+
+use crate::{export::Direction, protocol::{codec::RxFrame, addresses::*, commands::*}};
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Item {
+    Range(RangeInclusive<u8>),
+    Class(AddressClass)
+}
+
+/// A set of addresses or commands, e.g. `iu,85,50-5f`.
+///
+/// Values are hex, optionally as an inclusive `first-last` range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ByteSet(Vec<Item>);
+
+impl ByteSet {
+    pub fn contains(&self, value: u8) -> bool {
+        self.0.iter().any(|item| match item {
+            Item::Range(range) => range.contains(&value),
+            Item::Class(class) => AddressClass::of(value) == *class,
+        })
+    }
+
+    fn parse(s: &str, classes: impl Fn(&str) -> Option<Vec<Item>>) -> Result<Self> {
+        let hex = |value: &str| u8::from_str_radix(value.trim(), 16)
+            .with_context(|| format!("invalid hex value '{value}'"));
+
+        let mut items = Vec::new();
+
+        for part in s.split(',').map(str::trim) {
+            if let Some(class) = classes(part) {
+                items.extend(class);
+            } else if let Some((first, last)) = part.split_once('-') {
+                let (first, last) = (hex(first)?, hex(last)?);
+                if first > last {
+                    bail!("invalid range '{part}'");
+                }
+                items.push(Item::Range(first..=last));
+            } else {
+                let value = hex(part)?;
+                items.push(Item::Range(value..=value));
+            }
+        }
+
+        Ok(Self(items))
+    }
+
+    /// Parse a set of addresses. Also accepts the classes `main`, `sub`, `wrc`, `iu`, `erv`,
+    /// `broadcast` (all broadcast addresses) and `tracking` (the tracking broadcast).
+    pub fn addresses(s: &str) -> Result<Self> {
+        Self::parse(s, |name| match name {
+            "wrc" => Some(vec![Item::Class(AddressClass::MainWrc), Item::Class(AddressClass::SubWrc)]),
+            "tracking" => Some(vec![Item::Range(TRACKING_BROADCAST..=TRACKING_BROADCAST)]),
+            "other" => None,
+            name => AddressClass::from_name(name).map(|class| vec![Item::Class(class)])
+        })
+    }
+
+    /// Parse a set of commands. Also accepts the classes `handover` (`0xc4`, `0xc5` and `0xd1`)
+    /// and `tracking` (`0xf9`-`0xfe`).
+    pub fn commands(s: &str) -> Result<Self> {
+        Self::parse(s, |name| match name {
+            "handover" => Some([CommandC4Request::ID, CommandC5Request::ID, CommandD1::ID].map(|cmd| Item::Range(cmd..=cmd)).to_vec()),
+            "tracking" => Some(vec![Item::Range(0xf9..=TrackingAddressAssigned::ID)]),
+            _ => None
+        })
+    }
+}
+
+/// Selects the frames to show in the `dump` tool.
+#[derive(Debug, Default)]
+pub struct FrameFilter {
+    pub src: Option<ByteSet>,
+    pub dst: Option<ByteSet>,
+    pub cmd: Option<ByteSet>,
+    pub exclude_cmd: Option<ByteSet>,
+
+    /// Hide replies that are identical to the previous reply with the same source, destination and command.
+    pub only_changes: bool,
+
+    last_replies: HashMap<(u8, u8, u8), Vec<u8>>
+}
+
+impl FrameFilter {
+    /// Should the frame be shown? Corrupted frames are only shown without address or command filters.
+    pub fn matches(&mut self, frame: &RxFrame) -> bool {
+        let (id, data) = match frame {
+            RxFrame::Long(frame) => (frame.id, &frame.data[..]),
+            RxFrame::Short(frame) => (frame.id, &frame.data[..]),
+            RxFrame::Corrupted(_) => {
+                return self.src.is_none() && self.dst.is_none() && self.cmd.is_none() && self.exclude_cmd.is_none()
+            }
+        };
+
+        let included = |set: &Option<ByteSet>, value| set.as_ref().is_none_or(|set| set.contains(value));
+
+        if !included(&self.src, id.src) || !included(&self.dst, id.dst) || !included(&self.cmd, id.cmd) {
+            return false
+        }
+
+        if self.exclude_cmd.as_ref().is_some_and(|set| set.contains(id.cmd)) {
+            return false
+        }
+
+        if self.only_changes && Direction::of(id) == Some(Direction::Reply) {
+            let last = self.last_replies.insert(id.into(), data.to_vec());
+            if last.as_deref() == Some(data) {
+                return false
+            }
+        }
+
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::codec::{LongFrame, ShortFrame};
+
+    use super::*;
+
+    #[test]
+    fn test_frame_filter() {
+        let addresses = ByteSet::addresses("iu, 85, 50-5f").unwrap();
+        assert!(addresses.contains(0x20) && addresses.contains(0x3f) && addresses.contains(SUB_WRC) && addresses.contains(0x5f));
+        assert!(!addresses.contains(MAIN_WRC) && !addresses.contains(0x60));
+
+        assert!(ByteSet::addresses("wrc").unwrap().contains(MAIN_WRC));
+        assert!(ByteSet::addresses("5f-50").is_err());
+        assert!(ByteSet::addresses("lounge").is_err());
+        assert!(ByteSet::commands("iu").is_err());
+
+        let mut filter = FrameFilter {
+            src: Some(ByteSet::addresses("wrc,iu").unwrap()),
+            exclude_cmd: Some(ByteSet::commands("handover,tracking").unwrap()),
+            only_changes: true,
+            ..Default::default()
+        };
+
+        let response = |byte4| RxFrame::Long(LongFrame::new(0x20, MAIN_WRC, 0x52, [0x4a, 0x4c, 0x4e, 0xf8, byte4, 0x10, 0x00, 0x4e]));
+        let request = RxFrame::Long(LongFrame::new(MAIN_WRC, 0x20, 0x52, [0; 8]));
+
+        assert!(filter.matches(&request));
+        assert!(filter.matches(&response(0x22)));
+        assert!(filter.matches(&request));
+        assert!(!filter.matches(&response(0x22)));
+        assert!(filter.matches(&response(0x62)));
+
+        assert!(!filter.matches(&RxFrame::Long(LongFrame::new(MAIN_WRC, SUB_WRC, CommandC4Request::ID, [0; 8]))));
+        assert!(!filter.matches(&RxFrame::Short(ShortFrame::new(0x07, MAIN_WRC, TrackingHello::ID, [0xef]))));
+        assert!(!filter.matches(&RxFrame::Long(LongFrame::new(0x50, MAIN_WRC, 0x52, [0; 8]))));
+        assert!(!filter.matches(&RxFrame::Corrupted(vec![0x32])));
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{codec::{RxFrame, Unpack}, commands::*, addresses::*};
+
+
+/// The IUs and ERVs present on the bus.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BusInventory {
+    pub indoor_units: BTreeSet<u8>,
+    pub ervs: BTreeSet<u8>
+}
+
+/// Builds a [BusInventory] by passively watching the main WRC track and enumerate the bus.
+///
+/// Tracking starts with a `0xfb` (`TrackingStart`) broadcast, where the main WRC assigns
+/// unit numbers with `0xfd`. It then enumerates every IU and ERV address with `0x52` and `0x54`.
+/// Only the units that reply are present.
+/// Enumeration is complete when the main WRC hands over to the sub WRC with a `0xc4` marked
+/// for normal operation.
+#[derive(Default)]
+pub struct EnumerationObserver {
+    in_progress: bool,
+
+    /// Unit numbers assigned with `0xfd` during tracking.
+    assigned: BTreeSet<u8>,
+
+    inventory: BusInventory
+}
+
+impl EnumerationObserver {
+    /// Is the main WRC currently tracking/enumerating the bus?
+    pub fn in_progress(&self) -> bool {
+        self.in_progress
+    }
+
+    /// Observe a frame. Returns the inventory once enumeration completes.
+    pub fn observe(&mut self, frame: &RxFrame) -> Option<BusInventory> {
+        let id = frame.id()?;
+
+        match id.into() {
+            (MAIN_WRC, TRACKING_BROADCAST, TrackingCommandFB::ID) => {
+                let cmd = frame.unpack_unchecked::<TrackingCommandFB>().ok()?;
+
+                if let TrackingDiscoverState::TrackingStart = cmd.state {
+                    if !self.in_progress {
+                        println!("bus tracking started");
+                    }
+
+                    self.in_progress = true;
+                    self.assigned.clear();
+                    self.inventory = BusInventory::default();
+                }
+            },
+
+            (MAIN_WRC, _, TrackingAssignAddress::ID) if self.in_progress => {
+                if let RxFrame::Short(_) = frame {
+                    let cmd = frame.unpack_unchecked::<TrackingAssignAddress>().ok()?;
+                    self.assigned.insert(cmd.address);
+                }
+            },
+
+            (src @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, MAIN_WRC, Command52IndoorUnitResponse::ID | Command54IndoorUnitResponse::ID) if self.in_progress => {
+                self.inventory.indoor_units.insert(src);
+            },
+
+            (src @ ERV_FIRST..=ERV_LAST, MAIN_WRC, Command52ErvResponse::ID | Command54IndoorUnitResponse::ID) if self.in_progress => {
+                self.inventory.ervs.insert(src);
+            },
+
+            (MAIN_WRC, SUB_WRC, CommandC4Request::ID) if self.in_progress => {
+                let cmd = frame.unpack_unchecked::<CommandC4Request>().ok()?;
+
+                // `0x00` during enumeration, `0x01` during normal operation
+                if cmd.unknown_byte0 == 0x01 {
+                    self.in_progress = false;
+
+                    let found = self.inventory.indoor_units.len() + self.inventory.ervs.len();
+                    if found != self.assigned.len() {
+                        println!("bus enumeration: {} units were assigned addresses but {found} replied", self.assigned.len());
+                    }
+
+                    return Some(self.inventory.clone());
+                }
+            },
+
+            _ => ()
+        }
+
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::capture::parse_frames;
+
+    use super::*;
+
+    fn observe_capture(path: &str) -> Option<BusInventory> {
+        let capture = std::fs::read_to_string(path).unwrap();
+
+        let mut observer = EnumerationObserver::default();
+
+        parse_frames(&capture).unwrap().iter()
+            .find_map(|captured| observer.observe(&captured.frame))
+    }
+
+    #[test]
+    fn test_enumeration_inventory() {
+        let inventory = observe_capture("dumps/wrc-main+sub-boot-1IU.txt").unwrap();
+        assert_eq!(inventory.indoor_units, BTreeSet::from([0x20]));
+        assert!(inventory.ervs.is_empty());
+
+        let inventory = observe_capture("dumps/wrc-main+sub-boot-2IU(1emu).txt").unwrap();
+        assert_eq!(inventory.indoor_units, BTreeSet::from([0x20, 0x21]));
+
+        let inventory = observe_capture("dumps/wrc-main+sub-boot-2IU(1emu)-different-address.txt").unwrap();
+        assert_eq!(inventory.indoor_units, BTreeSet::from([0x20, 0x26]));
+    }
+}
@@ -1,4 +1,23 @@
 
 pub mod config;
 pub mod protocol;
-pub mod bus;
\ No newline at end of file
+pub mod bus;
+pub mod reconnect;
+pub mod capture;
+pub mod export;
+pub mod filter;
+pub mod dissector;
+pub mod bitdiff;
+pub mod stats;
+pub mod recorder;
+pub mod bridge;
+pub mod replay;
+pub mod nng_port;
+pub mod subwrc;
+pub mod inventory;
+pub mod tracker;
+pub mod capabilities;
+pub mod cache;
+pub mod controller;
+pub mod mqtt;
+pub mod settings;
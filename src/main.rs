@@ -1,57 +1,138 @@
-use std::{time::Duration, thread};
+use std::path::PathBuf;
 
-use anyhow::{Result, bail};
-use futures::{StreamExt, TryStream, TryStreamExt};
-use samsunghvac2mqtt::protocol::codec::WrcBusProtocolCodec;
-use tokio::{sync::{broadcast::{Sender, self, Receiver}, mpsc}, select, time::sleep, net::TcpStream};
-use tokio_serial::{SerialPortBuilderExt};
-use tokio_util::codec::Framed;
+use anyhow::Result;
+use clap::Parser;
+use samsunghvac2mqtt::{reconnect::{ReconnectingPort, Backoff}, subwrc, mqtt, settings::{Settings, MQTT_PASSWORD_VAR, parse_cli_mqtt_url}};
 
 
-use std::fmt::Debug;
+mod iuemu;
 
 
+/// Bridge between a Samsung HVAC WRC bus and MQTT
+///
+/// Settings are read from the config file (if given), then overridden by any options given here.
+/// The MQTT password can only be given in the config file or the SAMSUNGHVAC2MQTT_MQTT_PASSWORD
+/// environment variable, so that it doesn't show up in the process list.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Path of the TOML config file
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// URL of the port to connect to
+    #[arg(long)]
+    port: Option<String>,
+
+    /// MQTT broker URL, e.g. mqtt://user@host:1883 (without a password)
+    #[arg(long, value_parser = parse_cli_mqtt_url)]
+    mqtt: Option<String>,
+
+    #[arg(long)]
+    mqtt_username: Option<String>,
+
+    /// Prefix of the MQTT topics
+    #[arg(long)]
+    topic_prefix: Option<String>,
+
+    /// Minimum time between polls of the IUs, in milliseconds
+    #[arg(long)]
+    poll_interval_ms: Option<u64>,
+
+    /// Force a bus reset on start to discover the IUs/ERVs on the bus
+    #[arg(long)]
+    force_reset: Option<bool>,
+
+    /// Don't allow the settings of the IUs to be changed
+    #[arg(long)]
+    read_only: bool,
+
+    /// Never transmit on the bus
+    #[arg(long)]
+    listen_only: bool,
+
+    /// File to save the bus topology and last known IU states to, and restore them from on start
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+}
 
-mod iuemu;
-mod subwrc;
+impl Args {
+    fn apply(self, settings: &mut Settings) {
+        if let Some(port) = self.port {
+            settings.port.url = port;
+        }
 
+        if let Some(mqtt) = self.mqtt {
+            settings.mqtt.url = mqtt;
+        }
 
+        if let Some(username) = self.mqtt_username {
+            settings.mqtt.username = Some(username);
+        }
 
+        if let Ok(password) = std::env::var(MQTT_PASSWORD_VAR) {
+            settings.mqtt.password = Some(password);
+        }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    //let mut stream = TcpStream::connect("192.168.2.104:8899")?;
+        if let Some(topic_prefix) = self.topic_prefix {
+            settings.mqtt.topic_prefix = topic_prefix;
+        }
 
-    // let mut port = tokio_serial::new("/dev/ttyUSB0", 2400)
-    //     .stop_bits(tokio_serial::StopBits::One)
-    //     .parity(tokio_serial::Parity::Even)
-    //     .open_native_async()?;
+        if let Some(poll_interval_ms) = self.poll_interval_ms {
+            settings.bus.poll_interval_ms = poll_interval_ms;
+        }
 
-    // let port = TcpStream::connect("192.168.2.104:8899").await?;
+        if let Some(force_reset) = self.force_reset {
+            settings.bus.force_reset = force_reset;
+        }
 
-    let port = TcpStream::connect("localhost:3456").await?;
+        settings.bus.read_only |= self.read_only;
 
+        if let Some(state_file) = self.state_file {
+            settings.bus.state_file = Some(state_file);
+        }
 
+        if self.listen_only {
+            settings.bus.listen_only = true;
+
+            // a bus reset can't be forced without transmitting
+            if self.force_reset.is_none() {
+                settings.bus.force_reset = false;
+            }
+        }
+    }
+}
 
 
-    let framed = Box::new(Framed::new(port, WrcBusProtocolCodec::new()));
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
 
+    let mut settings = match &args.config {
+        Some(path) => Settings::load(path)?,
+        None => Settings::default()
+    };
 
-    
+    args.apply(&mut settings);
 
-    // framed.inspect_ok(|frame| {
-    //     // handle tracking polarity
-    //     // if frame.id() == Some((MAIN_WRC, TRACKING_BROADCAST, TrackingPolarityDetect::ID)) {
+    settings.validate()?;
 
-    //     // }
-    // });
+    let url = settings.port_url()?;
 
+    let port = ReconnectingPort::open(url, Backoff::default()).await?;
 
+    let mut events = port.subscribe();
+    tokio::spawn(async move {
+        while let Ok(event) = events.recv().await {
+            println!("port: {event:?}");
+        }
+    });
 
-    let subwrc_task = tokio::spawn(subwrc::sub_wrc_task(framed));
+    let framed = Box::new(port);
 
+    let (controller, task) = subwrc::sub_wrc_task(framed, settings.sub_wrc_options()?);
 
-    thread::park();
+    tokio::try_join!(task, mqtt::mqtt_task(settings.mqtt_options()?, controller))?;
 
     Ok(())
 }
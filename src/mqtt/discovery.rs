@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::{controller::SETPOINT_RANGE, protocol::iu::{IndoorUnitCapabilities, IndoorUnitField, IndoorUnitMode, IndoorUnitFanSpeed}};
+
+
+/// Default Home Assistant discovery prefix.
+pub const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// The entities published for each IU, as (component, object id).
+const INDOOR_UNIT_ENTITIES: [(&str, &str); 10] = [
+    ("climate", "climate"),
+    ("sensor", "evaporator_inlet_temperature"),
+    ("sensor", "evaporator_outlet_temperature"),
+    ("sensor", "humidity"),
+    ("sensor", "co2"),
+    ("binary_sensor", "defrost"),
+    ("binary_sensor", "clean_filter"),
+    // a `switch` once there's a known way to set away (see the status in the README)
+    ("binary_sensor", "away"),
+    ("switch", "s_plasma_ion"),
+    ("switch", "humidifier"),
+];
+
+/// Home Assistant name of an IU mode.
+pub fn hvac_mode_name(mode: IndoorUnitMode) -> &'static str {
+    match mode {
+        IndoorUnitMode::Fan => "fan_only",
+        mode => mode.name()
+    }
+}
+
+/// IU mode from its Home Assistant name.
+pub fn hvac_mode_from_name(name: &str) -> Option<IndoorUnitMode> {
+    match name {
+        "fan_only" => Some(IndoorUnitMode::Fan),
+        name => IndoorUnitMode::from_name(name)
+    }
+}
+
+fn merge(config: &mut Value, other: Value) {
+    if let (Some(config), Value::Object(other)) = (config.as_object_mut(), other) {
+        config.extend(other);
+    }
+}
+
+/// Builds Home Assistant MQTT discovery configs for the units on the bus.
+///
+/// Each config is a (topic, payload) pair, to be published retained.
+/// An empty payload removes the entity.
+pub struct Discovery<'a> {
+    pub discovery_prefix: &'a str,
+    pub topic_prefix: &'a str,
+
+    /// Device names of the IUs, by address. Unnamed IUs are named after their address.
+    pub indoor_unit_names: &'a BTreeMap<u8, String>,
+}
+
+impl Discovery<'_> {
+    /// Node id of a unit, also used for its unique ids.
+    ///
+    /// Only `[A-Za-z0-9_-]` are allowed in node ids, so anything else in the topic prefix (e.g. a `/`) is replaced with `_`.
+    fn node_id(&self, address: u8) -> String {
+        let prefix: String = self.topic_prefix.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+            .collect();
+
+        format!("{prefix}_{address:02x}")
+    }
+
+    fn config_topic(&self, component: &str, address: u8, object_id: &str) -> String {
+        format!("{}/{component}/{}/{object_id}/config", self.discovery_prefix, self.node_id(address))
+    }
+
+    fn topic(&self, address: u8, attribute: &str) -> String {
+        format!("{}/{address:02x}/{attribute}", self.topic_prefix)
+    }
+
+    /// Config common to every entity of a unit.
+    ///
+    /// Entities without a name are named after the device.
+    fn entity(&self, address: u8, object_id: &str, name: Option<&str>, device_name: &str) -> Value {
+        json!({
+            "name": name,
+            "unique_id": format!("{}_{object_id}", self.node_id(address)),
+            "availability_topic": format!("{}/availability", self.topic_prefix),
+            "device": {
+                "identifiers": [self.node_id(address)],
+                "name": device_name,
+                "manufacturer": "Samsung",
+            }
+        })
+    }
+
+    fn indoor_unit_entity(&self, address: u8, component: &str, object_id: &str, capabilities: &IndoorUnitCapabilities) -> Option<Value> {
+        let (name, specific) = match (component, object_id) {
+            ("climate", _) => {
+                let modes: Vec<_> = std::iter::once("off")
+                    .chain(IndoorUnitMode::ALL.into_iter().map(hvac_mode_name))
+                    .collect();
+
+                let fan_modes: Vec<_> = IndoorUnitFanSpeed::ALL.iter().map(IndoorUnitFanSpeed::name).collect();
+
+                (None, json!({
+                    "modes": modes,
+                    "mode_state_topic": self.topic(address, "hvac_mode"),
+                    "mode_command_topic": self.topic(address, "hvac_mode/set"),
+                    "fan_modes": fan_modes,
+                    "fan_mode_state_topic": self.topic(address, "fan_speed"),
+                    "fan_mode_command_topic": self.topic(address, "fan_speed/set"),
+                    "temperature_state_topic": self.topic(address, "setpoint"),
+                    "temperature_command_topic": self.topic(address, "setpoint/set"),
+                    "current_temperature_topic": self.topic(address, "room_temperature"),
+                    "min_temp": SETPOINT_RANGE.start(),
+                    "max_temp": SETPOINT_RANGE.end(),
+                    "temp_step": 1,
+                    "precision": 1.0,
+                    "temperature_unit": "C",
+                }))
+            },
+
+            ("sensor", "evaporator_inlet_temperature" | "evaporator_outlet_temperature") => {
+                let name = if object_id == "evaporator_inlet_temperature" { "Evaporator inlet temperature" } else { "Evaporator outlet temperature" };
+
+                (Some(name), json!({
+                    "state_topic": self.topic(address, object_id),
+                    "device_class": "temperature",
+                    "unit_of_measurement": "°C",
+                    "state_class": "measurement",
+                    "entity_category": "diagnostic",
+                }))
+            },
+
+            // raw values, scale unknown
+            ("sensor", _) => {
+                if !capabilities.supports(IndoorUnitField::Humidity) {
+                    return None
+                }
+
+                (Some(if object_id == "humidity" { "Humidity" } else { "CO2" }), json!({
+                    "state_topic": self.topic(address, object_id),
+                    "state_class": "measurement",
+                }))
+            },
+
+            ("binary_sensor", _) => {
+                let mut config = json!({ "state_topic": self.topic(address, object_id) });
+
+                let name = match object_id {
+                    "defrost" => "Defrost",
+                    "clean_filter" => {
+                        config["device_class"] = json!("problem");
+                        "Clean filter"
+                    },
+                    _ => {
+                        if !capabilities.supports(IndoorUnitField::AwayMds) {
+                            return None
+                        }
+                        "Away"
+                    }
+                };
+
+                (Some(name), config)
+            },
+
+            ("switch", _) => {
+                let (name, supported) = match object_id {
+                    "s_plasma_ion" => ("S-Plasma ion", capabilities.supports(IndoorUnitField::SPlasmaIon)),
+                    _ => ("Humidifier", capabilities.supports(IndoorUnitField::Humidifier))
+                };
+
+                if !supported {
+                    return None
+                }
+
+                (Some(name), json!({
+                    "state_topic": self.topic(address, object_id),
+                    "command_topic": self.topic(address, &format!("{object_id}/set")),
+                }))
+            },
+
+            _ => unreachable!()
+        };
+
+        let device_name = self.indoor_unit_names.get(&address).cloned()
+            .unwrap_or_else(|| format!("Samsung Indoor Unit {address:02x}"));
+
+        let mut config = self.entity(address, object_id, name, &device_name);
+        merge(&mut config, specific);
+
+        Some(config)
+    }
+
+    /// Configs for an IU's entities. Entities for features it doesn't support are removed.
+    pub fn indoor_unit(&self, address: u8, capabilities: &IndoorUnitCapabilities) -> Vec<(String, String)> {
+        INDOOR_UNIT_ENTITIES.iter()
+            .map(|(component, object_id)| {
+                let payload = self.indoor_unit_entity(address, component, object_id, capabilities)
+                    .map(|config| config.to_string())
+                    .unwrap_or_default();
+
+                (self.config_topic(component, address, object_id), payload)
+            })
+            .collect()
+    }
+
+    /// Remove all of an IU's entities.
+    pub fn remove_indoor_unit(&self, address: u8) -> Vec<(String, String)> {
+        INDOOR_UNIT_ENTITIES.iter()
+            .map(|(component, object_id)| (self.config_topic(component, address, object_id), String::new()))
+            .collect()
+    }
+
+    /// Config for an ERV's power entity.
+    ///
+    /// ERVs can't be controlled yet, and Home Assistant's `fan` requires a command topic,
+    /// so their power is a `binary_sensor` until then.
+    pub fn erv(&self, address: u8) -> (String, String) {
+        let mut config = self.entity(address, "power", None, &format!("Samsung ERV {address:02x}"));
+        merge(&mut config, json!({
+            "state_topic": self.topic(address, "power"),
+            "device_class": "running",
+        }));
+
+        (self.config_topic("binary_sensor", address, "power"), config.to_string())
+    }
+
+    pub fn remove_erv(&self, address: u8) -> (String, String) {
+        (self.config_topic("binary_sensor", address, "power"), String::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indoor_unit_discovery() {
+        let names = BTreeMap::from([(0x20, "Living Room".to_string())]);
+        let discovery = Discovery { discovery_prefix: "homeassistant", topic_prefix: "samsunghvac", indoor_unit_names: &names };
+
+        let capabilities = IndoorUnitCapabilities { s_plasma_ion: Some(true), humidifier: Some(false), away_mds: Some(false), ..Default::default() };
+        let configs = discovery.indoor_unit(0x20, &capabilities);
+
+        let (topic, climate) = &configs[0];
+        assert_eq!(topic, "homeassistant/climate/samsunghvac_20/climate/config");
+
+        let climate: Value = serde_json::from_str(climate).unwrap();
+        assert_eq!(climate["modes"], json!(["off", "auto", "heat", "cool", "dry", "fan_only"]));
+        assert_eq!(climate["fan_modes"], json!(["auto", "low", "medium", "high"]));
+        assert_eq!(climate["current_temperature_topic"], "samsunghvac/20/room_temperature");
+        assert_eq!(climate["min_temp"], 16);
+        assert_eq!(climate["max_temp"], 30);
+
+        let payload = |topic: &str| configs.iter().find(|(t, _)| t == topic).unwrap().1.clone();
+
+        assert!(!payload("homeassistant/switch/samsunghvac_20/s_plasma_ion/config").is_empty());
+        assert!(payload("homeassistant/switch/samsunghvac_20/humidifier/config").is_empty());
+        assert!(payload("homeassistant/binary_sensor/samsunghvac_20/away/config").is_empty());
+
+        let sensor: Value = serde_json::from_str(&payload("homeassistant/sensor/samsunghvac_20/evaporator_inlet_temperature/config")).unwrap();
+        assert_eq!(sensor["state_topic"], "samsunghvac/20/evaporator_inlet_temperature");
+        assert_eq!(sensor["device"]["identifiers"], json!(["samsunghvac_20"]));
+        assert_eq!(sensor["device"]["name"], "Living Room");
+
+        // the IU hasn't answered `0x55`
+        assert!(payload("homeassistant/sensor/samsunghvac_20/humidity/config").is_empty());
+        assert!(payload("homeassistant/sensor/samsunghvac_20/co2/config").is_empty());
+
+        let configs = discovery.indoor_unit(0x20, &IndoorUnitCapabilities { humidity_co2: true, ..capabilities });
+        let payload = |topic: &str| configs.iter().find(|(t, _)| t == topic).unwrap().1.clone();
+        assert!(!payload("homeassistant/sensor/samsunghvac_20/humidity/config").is_empty());
+        assert!(!payload("homeassistant/sensor/samsunghvac_20/co2/config").is_empty());
+
+        assert!(discovery.remove_indoor_unit(0x20).iter()
+            .all(|(topic, payload)| payload.is_empty() && configs.iter().any(|(t, _)| t == topic)));
+    }
+
+    #[test]
+    fn test_erv_discovery() {
+        let names = BTreeMap::new();
+        let discovery = Discovery { discovery_prefix: "homeassistant", topic_prefix: "samsunghvac", indoor_unit_names: &names };
+
+        let (topic, config) = discovery.erv(0x50);
+        assert_eq!(topic, "homeassistant/binary_sensor/samsunghvac_50/power/config");
+
+        // can't be controlled yet
+        let config: Value = serde_json::from_str(&config).unwrap();
+        assert_eq!(config["state_topic"], "samsunghvac/50/power");
+        assert!(config.get("command_topic").is_none());
+
+        assert_eq!(discovery.remove_erv(0x50), (topic, String::new()));
+    }
+    #[test]
+    fn test_discovery_topic_prefix_with_levels() {
+        let names = BTreeMap::new();
+        let discovery = Discovery { discovery_prefix: "homeassistant", topic_prefix: "site1/hvac", indoor_unit_names: &names };
+
+        let configs = discovery.indoor_unit(0x20, &IndoorUnitCapabilities::default());
+
+        let (topic, climate) = &configs[0];
+        assert_eq!(topic, "homeassistant/climate/site1_hvac_20/climate/config");
+
+        // the state and command topics keep the prefix as is
+        let climate: Value = serde_json::from_str(climate).unwrap();
+        assert_eq!(climate["unique_id"], "site1_hvac_20_climate");
+        assert_eq!(climate["device"]["identifiers"], json!(["site1_hvac_20"]));
+        assert_eq!(climate["mode_command_topic"], "site1/hvac/20/hvac_mode/set");
+        assert_eq!(climate["availability_topic"], "site1/hvac/availability");
+
+        assert_eq!(discovery.erv(0x50).0, "homeassistant/binary_sensor/site1_hvac_50/power/config");
+    }
+}
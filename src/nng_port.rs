@@ -0,0 +1,212 @@
+use std::{pin::Pin, task::{Context, Poll}};
+
+use bytes::BytesMut;
+use futures::{Stream, Sink};
+use nng_futures::{AsyncSocket, Socket, Protocol, Message};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::codec::Encoder;
+use url::Url;
+use anyhow::{Result, Context as _, bail};
+
+use crate::protocol::codec::{RxFrame, TxFrame, WrcBusProtocolCodec};
+
+
+fn io_error(err: nng_futures::Error) -> std::io::Error {
+    std::io::Error::other(err)
+}
+
+/// A port that carries whole encoded frames as messages over an NNG bus (`bus0`) socket.
+///
+/// Unlike a byte stream, each frame is delivered atomically, so frames from
+/// multiple participants can never interleave.
+///
+/// `nng+bus://host:port` dials a listening peer, `nng+bus+listen://host:port` listens for peers.
+/// Omit the host (e.g. `nng+bus:///run/samsunghvac.ipc`) to use IPC instead of TCP.
+///
+/// NNG bus sockets only deliver messages to directly connected peers, so the listening
+/// side also relays every message it receives to all of its other peers (excluding the sender).
+/// This gives a star topology where every participant sees every frame.
+pub struct NngPort {
+    socket: Socket,
+    codec: WrcBusProtocolCodec,
+    rx_frames: mpsc::Receiver<std::io::Result<RxFrame>>,
+    receiver: JoinHandle<()>
+}
+
+impl NngPort {
+    pub fn open(url: &Url) -> Result<Self> {
+        let listen = match url.scheme() {
+            "nng+bus" => false,
+            "nng+bus+listen" => true,
+            other => bail!("url scheme {other} not supported by nng ports")
+        };
+
+        let address = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("tcp://{host}:{port}"),
+            (Some(_), None) => bail!("{} requires a port number to be specified in the url: {url}", url.scheme()),
+            (None, _) => format!("ipc://{}", url.path())
+        };
+
+        let socket = if listen {
+            // raw mode tags received messages with the originating pipe, which is used
+            // to exclude the originator when relaying
+            let socket = Socket::new_raw(Protocol::Bus0)?;
+
+            socket.listen(&address)
+                .with_context(|| format!("failed to listen on {address}"))?;
+
+            socket
+        } else {
+            let socket = Socket::new(Protocol::Bus0)?;
+
+            // dial in the background, re-dialing if the listener goes away
+            socket.dial_async(&address)
+                .with_context(|| format!("failed to dial {address}"))?;
+
+            socket
+        };
+
+        let (tx_frames, rx_frames) = mpsc::channel(32);
+
+        let receiver = tokio::spawn({
+            let socket = AsyncSocket::new(socket.clone())?;
+
+            async move {
+                loop {
+                    let msg = match socket.recv().await {
+                        Ok(msg) => msg,
+                        Err(nng_futures::Error::Closed) => break,
+                        Err(err) => {
+                            let _ = tx_frames.send(Err(io_error(err))).await;
+                            break;
+                        }
+                    };
+
+                    // every message is a single frame, anything else is passed on as corrupted
+                    let frame = RxFrame::from_bytes(&msg);
+
+                    if listen {
+                        if let Err((_, err)) = socket.send(msg).await {
+                            println!("{address}: failed to relay message: {err}");
+                        }
+                    }
+
+                    if tx_frames.send(Ok(frame)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            socket,
+            codec: WrcBusProtocolCodec::new(),
+            rx_frames,
+            receiver
+        })
+    }
+}
+
+impl Drop for NngPort {
+    fn drop(&mut self) {
+        self.receiver.abort();
+        self.socket.close();
+    }
+}
+
+impl Stream for NngPort {
+    type Item = std::io::Result<RxFrame>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx_frames.poll_recv(cx)
+    }
+}
+
+impl Sink<TxFrame> for NngPort {
+    type Error = std::io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: TxFrame) -> Result<(), Self::Error> {
+        let mut buf = BytesMut::new();
+        self.codec.encode(frame, &mut buf)?;
+
+        // bus sockets never block on send -- messages are dropped if a peer can't keep up
+        self.socket.send(Message::from(&buf[..]))
+            .map_err(|(_, err)| io_error(err))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::time::timeout;
+
+    use crate::protocol::codec::{LongFrame, ShortFrame};
+
+    use super::*;
+
+    async fn next_frame(port: &mut NngPort) -> Option<RxFrame> {
+        timeout(Duration::from_millis(500), port.next()).await.ok().flatten().map(|frame| frame.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_nng_bus_relay() {
+        let path = std::env::temp_dir().join(format!("samsunghvac2mqtt-test-nng-{}.ipc", std::process::id()));
+        let url = |scheme: &str| Url::parse(&format!("{scheme}://{}", path.display())).unwrap();
+
+        let mut listener = NngPort::open(&url("nng+bus+listen")).unwrap();
+        let mut a = NngPort::open(&url("nng+bus")).unwrap();
+        let mut b = NngPort::open(&url("nng+bus")).unwrap();
+
+        // wait for the dialers to connect
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let long_frame = LongFrame::new(0x20, 0x84, 0x52, [0x4b, 0x4c, 0x63, 0xf8, 0x81, 0x10, 0x00, 0x6f]);
+        a.send(TxFrame::Long(long_frame.clone())).await.unwrap();
+
+        // the listener receives it and relays it to b, but not back to a
+        assert_eq!(next_frame(&mut listener).await.map(|frame| frame.to_bytes()), Some(long_frame.to_bytes()));
+        assert_eq!(next_frame(&mut b).await.map(|frame| frame.to_bytes()), Some(long_frame.to_bytes()));
+        assert!(next_frame(&mut a).await.is_none());
+
+        // the listener's own frames go to every peer
+        let short_frame = ShortFrame::new(0x84, 0xeb, 0xf9, [0x00]);
+        listener.send(TxFrame::Short(short_frame.clone())).await.unwrap();
+
+        for port in [&mut a, &mut b] {
+            assert_eq!(next_frame(port).await.map(|frame| frame.to_bytes()), Some(short_frame.to_bytes()));
+        }
+        assert!(next_frame(&mut listener).await.is_none());
+
+        // a message that isn't a whole frame is received as one corrupted frame, not resynchronised
+        let mut corrupted = long_frame.to_bytes();
+        corrupted[5] ^= 0xff;
+        a.socket.send(Message::from(&corrupted[..])).unwrap();
+        b.socket.send(Message::from(&long_frame.to_bytes()[..7])).unwrap();
+
+        let mut received = vec![];
+        while let Some(frame) = next_frame(&mut listener).await {
+            received.push(frame);
+        }
+        assert_eq!(received.len(), 2);
+        assert!(received.iter().all(|frame| frame.id().is_none()));
+        assert!(received.iter().any(|frame| frame.is_invalid_checksum() && frame.to_bytes() == corrupted));
+
+        drop((listener, a, b));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -13,4 +13,45 @@ pub const BROADCAST: u8 = 0xad;
 pub const ENUMERATION_BROADCAST: u8 = 0xc9;
 
 /// Address used by the main WRC to broadcast tracking frames/
-pub const TRACKING_BROADCAST: u8 = 0xeb;
\ No newline at end of file
+pub const TRACKING_BROADCAST: u8 = 0xeb;
+
+/// The kind of device (or broadcast) an address belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AddressClass {
+    MainWrc,
+    SubWrc,
+    IndoorUnit,
+    Erv,
+    Broadcast,
+    Other
+}
+
+impl AddressClass {
+    pub const ALL: [Self; 6] = [Self::MainWrc, Self::SubWrc, Self::IndoorUnit, Self::Erv, Self::Broadcast, Self::Other];
+
+    pub fn of(address: u8) -> Self {
+        match address {
+            MAIN_WRC => Self::MainWrc,
+            SUB_WRC => Self::SubWrc,
+            INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST => Self::IndoorUnit,
+            ERV_FIRST..=ERV_LAST => Self::Erv,
+            BROADCAST | ENUMERATION_BROADCAST | TRACKING_BROADCAST => Self::Broadcast,
+            _ => Self::Other
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MainWrc => "main",
+            Self::SubWrc => "sub",
+            Self::IndoorUnit => "iu",
+            Self::Erv => "erv",
+            Self::Broadcast => "broadcast",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|class| class.name() == name)
+    }
+}
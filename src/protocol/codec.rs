@@ -35,6 +35,13 @@ impl From<FrameId> for (u8,u8,u8) {
 pub trait Unpack<const S: usize> {
     fn unpack_as<T>(&self) -> Result<T, packed_struct::PackingError> where
         T: PackedStruct<ByteArray = [u8; S]> + Command;
+
+    /// Like [Unpack::unpack_as], but without running [Command::check], and failing instead
+    /// of panicking if the frame has the wrong size.
+    ///
+    /// For frames observed on the bus, which may not be what the checks expect.
+    fn unpack_unchecked<T>(&self) -> Result<T, packed_struct::PackingError> where
+        T: PackedStruct<ByteArray = [u8; S]> + Command;
 }
 
 
@@ -78,6 +85,26 @@ impl<const S: usize> FrameInner<S> {
 
         Ok(cmd)
     }
+
+    /// Unpack `Self` as a `Command` `PackedStruct`, without running [Command::check].
+    pub fn unpack_unchecked<T>(&self) -> Result<T, packed_struct::PackingError> where
+        T: PackedStruct<ByteArray = [u8; S]> + Command
+    {
+        T::unpack(&self.data)
+    }
+
+    /// The frame as sent on the wire, including the start/end markers and checksum.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+
+        bytes.push(FRAME_START);
+        bytes.extend([self.id.src, self.id.dst, self.id.cmd]);
+        bytes.extend(self.data);
+        bytes.push(bytes[1..].iter().checksum());
+        bytes.push(FRAME_END);
+
+        bytes
+    }
 }
 
 pub type LongFrame = FrameInner<8>;
@@ -89,6 +116,8 @@ pub type ShortFrame = FrameInner<1>;
 pub enum RxFrame {
     Long(LongFrame),
     Short(ShortFrame),
+
+    /// A frame with an invalid checksum, or bytes skipped to find the start of the next frame.
     Corrupted(Vec<u8>)
 }
 
@@ -101,6 +130,45 @@ impl RxFrame {
             RxFrame::Corrupted(_) => None,
         }
     }
+
+    /// The frame as received on the wire (see [FrameInner::to_bytes]).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RxFrame::Long(frame) => frame.to_bytes(),
+            RxFrame::Short(frame) => frame.to_bytes(),
+            RxFrame::Corrupted(data) => data.clone(),
+        }
+    }
+
+    /// Parse a frame as received on the wire (the inverse of [RxFrame::to_bytes]).
+    /// Anything that isn't a single valid frame is corrupted.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let valid = bytes.len() >= ShortFrame::SIZE
+            && bytes[0] == FRAME_START && bytes[bytes.len() - 1] == FRAME_END
+            && bytes[1..bytes.len() - 2].iter().checksum() == bytes[bytes.len() - 2];
+
+        let (src, dst, cmd) = match bytes {
+            [_, src, dst, cmd, ..] => (*src, *dst, *cmd),
+            _ => return RxFrame::Corrupted(bytes.to_vec())
+        };
+
+        match (valid, bytes.len()) {
+            (true, ShortFrame::SIZE) => RxFrame::Short(ShortFrame::new(src, dst, cmd, [bytes[4]])),
+            (true, LongFrame::SIZE) => RxFrame::Long(LongFrame::new(src, dst, cmd, bytes[4..12].try_into().unwrap())),
+            _ => RxFrame::Corrupted(bytes.to_vec())
+        }
+    }
+
+    /// Is this a whole frame with an invalid checksum (rather than skipped bytes)?
+    pub fn is_invalid_checksum(&self) -> bool {
+        match self {
+            RxFrame::Corrupted(data) => {
+                (data.len() == ShortFrame::SIZE || data.len() == LongFrame::SIZE)
+                    && data.first() == Some(&FRAME_START) && data.last() == Some(&FRAME_END)
+            },
+            _ => false
+        }
+    }
 }
 
 impl Unpack<{LongFrame::DATA_SIZE}> for RxFrame {
@@ -112,6 +180,14 @@ impl Unpack<{LongFrame::DATA_SIZE}> for RxFrame {
             panic!("tried to unpack")
         }
     }
+
+    fn unpack_unchecked<T>(&self) -> Result<T, packed_struct::PackingError> where
+        T: PackedStruct<ByteArray = [u8; LongFrame::DATA_SIZE]> + Command {
+        match self {
+            RxFrame::Long(frame) => frame.unpack_unchecked::<T>(),
+            other => Err(packed_struct::PackingError::BufferSizeMismatch { expected: LongFrame::DATA_SIZE, actual: other.to_bytes().len().saturating_sub(6) })
+        }
+    }
 }
 
 impl Unpack<{ShortFrame::DATA_SIZE}> for RxFrame {
@@ -123,6 +199,14 @@ impl Unpack<{ShortFrame::DATA_SIZE}> for RxFrame {
             panic!("tried to unpack")
         }
     }
+
+    fn unpack_unchecked<T>(&self) -> Result<T, packed_struct::PackingError> where
+        T: PackedStruct<ByteArray = [u8; ShortFrame::DATA_SIZE]> + Command {
+        match self {
+            RxFrame::Short(frame) => frame.unpack_unchecked::<T>(),
+            other => Err(packed_struct::PackingError::BufferSizeMismatch { expected: ShortFrame::DATA_SIZE, actual: other.to_bytes().len().saturating_sub(6) })
+        }
+    }
 }
 
 impl From<TxFrame> for RxFrame {
@@ -173,11 +257,11 @@ enum FramingError {
     FrameStartNotFound,
     #[error("end of frame marker not found")]
     FrameEndNotFound,
-    #[error("invalid checksum (expected {expected:x}, actual: {actual:x}) ")] // for frame {frame:x?}
+    #[error("invalid checksum (expected {expected:x}, actual: {actual:x}) for frame {frame:x?}")]
     InvalidChecksum {
         expected: u8,
         actual: u8,
-        //frame: Vec<u8>
+        frame: Vec<u8>
     },
 }
 
@@ -187,17 +271,25 @@ pub struct WrcBusProtocolCodec {
     start_time: SystemTime,
     last_rx_time: Option<SystemTime>,
     last_tx_time: Option<SystemTime>,
-    last_txrx_time: Option<SystemTime>
+    last_txrx_time: Option<SystemTime>,
+
+    /// Bytes skipped while looking for the start of a frame, returned as a corrupted frame
+    /// before the next frame.
+    skipped: Vec<u8>,
+
+    /// Frame read after skipping bytes, returned on the next call.
+    pending: Option<RxFrame>
 }
 
 impl WrcBusProtocolCodec {
     pub fn new() -> Self {
-        println!("XX  elapsed tx-delta rx-delta    delta");
         WrcBusProtocolCodec {
             start_time: SystemTime::now(),
             last_rx_time: None,
             last_tx_time: None,
-            last_txrx_time: None
+            last_txrx_time: None,
+            skipped: Vec::new(),
+            pending: None
         }
     }
 }
@@ -210,12 +302,12 @@ pub fn delta_ms(time: Option<SystemTime>) -> u128 {
     }
 }
 
-impl Decoder for WrcBusProtocolCodec {
-    type Item = RxFrame;
-
-    type Error = std::io::Error;
-
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+impl WrcBusProtocolCodec {
+    /// Read the next frame from `src`.
+    ///
+    /// At the end of the stream (`eof`), a short frame with an invalid checksum is reported as
+    /// corrupted rather than waiting to see if it's the start of a long frame.
+    fn read_frame(&mut self, src: &mut BytesMut, eof: bool) -> Result<Option<RxFrame>, std::io::Error> {
         fn try_read_frame<const S: usize>(src: &mut BytesMut, codec: &WrcBusProtocolCodec) -> Result<FrameInner<S>, FramingError> {
             let size = FrameInner::<S>::SIZE;
 
@@ -233,6 +325,14 @@ impl Decoder for WrcBusProtocolCodec {
                 return Err(FramingError::FrameEndNotFound)
             }
 
+            // only consume the frame once it's known to be valid, as a corrupted short frame
+            // may be the start of a long frame
+            let checksum = src[size - 2];
+            let expected_checksum = src[1..size - 2].iter().checksum();
+            if expected_checksum != checksum {
+                return Err(FramingError::InvalidChecksum { expected: expected_checksum, actual: checksum, frame: src[..size].to_vec() })
+            }
+
             let mut src = src.split_to(size);
 
             let start_delta_ms = delta_ms(Some(codec.start_time));
@@ -242,37 +342,42 @@ impl Decoder for WrcBusProtocolCodec {
             //println!("RX {start_delta_ms:8}          {rx_delta_ms:8} {txrx_delta_ms:8}: {:x?}", &src[..]);
 
             src.advance(1); // ignore frame start
-
-            let frame = {
-                let mut footer = src.split_off(src.len()-2);
-
-                let checksum = footer.get_u8();
-
-                let expected_checksum = src.iter().checksum();
-                if expected_checksum != checksum {
-                    return Err(FramingError::InvalidChecksum { expected: expected_checksum, actual: checksum })
-                }
-
-                FrameInner::<S> {
-                    id: FrameId {
-                        src: src.get_u8(),
-                        dst: src.get_u8(),
-                        cmd: src.get_u8()
-                    },
-                    data: src[..].try_into().unwrap(),
-                }
+            src.truncate(src.len() - 2); // ignore checksum and frame end
+
+            let frame = FrameInner::<S> {
+                id: FrameId {
+                    src: src.get_u8(),
+                    dst: src.get_u8(),
+                    cmd: src.get_u8()
+                },
+                data: src[..].try_into().unwrap(),
             };
 
             Ok(frame)
         }
 
+        /// Give up on finding the start of a frame after this many bytes.
+        const MAX_SKIPPED: usize = 256;
+
+        if let Some(frame) = self.pending.take() {
+            return Ok(Some(frame));
+        }
+
         loop {
             let frame = match try_read_frame(src, self) {
                 Ok(frame) => Ok(Some(RxFrame::Short(frame))),
 
-                Err(FramingError::FrameEndNotFound) => {
-                    try_read_frame(src, self)
-                        .map(|frame| Some(RxFrame::Long(frame)))
+                // not a short frame (or a corrupted one), try a long frame
+                Err(short_err @ (FramingError::FrameEndNotFound | FramingError::InvalidChecksum { .. })) => {
+                    let corrupted_short = matches!(short_err, FramingError::InvalidChecksum { .. });
+
+                    match try_read_frame(src, self) {
+                        Ok(frame) => Ok(Some(RxFrame::Long(frame))),
+                        Err(FramingError::BufferTooSmall) if corrupted_short && eof => Err(short_err),
+                        Err(FramingError::BufferTooSmall) => Err(FramingError::BufferTooSmall),
+                        Err(_) if corrupted_short => Err(short_err),
+                        Err(err) => Err(err)
+                    }
                 }
 
                 Err(err) => Err(err),
@@ -294,26 +399,59 @@ impl Decoder for WrcBusProtocolCodec {
                     // or, frame start marker found that doesn't have a corresponding end frame marker -- skip it
                     //      (likely the start frame marker was inside a previous packet and the stream has lost sync)
 
-                    // TODO: implement a limit on the amount of data we skip -- after a while the stream should be considered
-                    // unreadable and be an Error
+                    self.skipped.push(src[0]);
                     src.advance(1);
+
+                    if self.skipped.len() >= MAX_SKIPPED {
+                        return Ok(Some(RxFrame::Corrupted(std::mem::take(&mut self.skipped))));
+                    }
                     continue;
                 }
 
-                // TODO: handle checksum errors "gracefully"
-                // maybe only bail out after n checksum errors?
-                Err(FramingError::InvalidChecksum { expected, actual }) => {
-                    todo!();
-                    Ok(Some(RxFrame::Corrupted(vec![])))
+                // the frame is dropped, the next one is likely fine
+                Err(FramingError::InvalidChecksum { frame, .. }) => {
+                    src.advance(frame.len());
+                    Ok(Some(RxFrame::Corrupted(frame)))
                 }
-
             };
 
+            // report the skipped bytes first
+            if !self.skipped.is_empty() {
+                self.pending = frame?;
+                return Ok(Some(RxFrame::Corrupted(std::mem::take(&mut self.skipped))));
+            }
+
             return frame
         }
     }
 }
 
+impl Decoder for WrcBusProtocolCodec {
+    type Item = RxFrame;
+
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.read_frame(src, false)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(frame) = self.read_frame(src, true)? {
+            return Ok(Some(frame))
+        }
+
+        // no more data is coming to complete a frame
+        if !src.is_empty() || !self.skipped.is_empty() {
+            let mut data = std::mem::take(&mut self.skipped);
+            data.extend_from_slice(&src.split_to(src.len()));
+
+            return Ok(Some(RxFrame::Corrupted(data)))
+        }
+
+        Ok(None)
+    }
+}
+
 impl Encoder<TxFrame> for WrcBusProtocolCodec {
     type Error = std::io::Error;
 
@@ -329,6 +467,8 @@ impl Encoder<TxFrame> for WrcBusProtocolCodec {
             },
         };
 
+        let start = dst.len();
+
         dst.put_u8(FRAME_START);
         dst.put_u8(header.src);
         dst.put_u8(header.dst);
@@ -336,7 +476,7 @@ impl Encoder<TxFrame> for WrcBusProtocolCodec {
 
         dst.put(data);
 
-        let checksum = dst[1..dst.len()].iter().checksum();
+        let checksum = dst[start + 1..].iter().checksum();
         dst.put_u8(checksum);
         dst.put_u8(FRAME_END);
 
@@ -399,7 +539,36 @@ mod tests {
 
         // long frame with nested invalid short frame
         {
+            // byte 6 is a frame end marker, but the first 7 bytes aren't a valid short frame
+            let long_frame = LongFrame::new(0x20, 0x84, 0x52, [0x4b, 0x4c, 0x34, 0xf8, 0x81, 0x10, 0x00, 0x6f]).to_bytes();
+            assert_eq!(long_frame[6], FRAME_END);
+            assert_ne!(long_frame[5], long_frame[1..5].iter().checksum());
+
+            let mut codec = WrcBusProtocolCodec::new();
+            let mut buf = BytesMut::new();
+
+            // may be the start of a long frame
+            buf.put(&long_frame[..7]);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+
+            buf.put(&long_frame[7..]);
+            buf.put(&valid_short_frame[..]);
+
+            let frame = codec.decode(&mut buf).unwrap().unwrap();
+            assert!(matches!(frame, RxFrame::Long(_)));
+            assert_eq!(frame.to_bytes(), long_frame);
 
+            let frame = codec.decode(&mut buf).unwrap().unwrap();
+            assert_eq!(frame.to_bytes(), valid_short_frame);
+
+            // at the end of the stream, it's a corrupted short frame after all
+            buf.put(&long_frame[..7]);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+
+            let frame = codec.decode_eof(&mut buf).unwrap().unwrap();
+            assert!(frame.is_invalid_checksum());
+            assert_eq!(frame.to_bytes(), &long_frame[..7]);
+            assert!(codec.decode_eof(&mut buf).unwrap().is_none());
         }
 
         // long frame with nested valid short frame
@@ -408,7 +577,15 @@ mod tests {
             ambiguious_frame[5] = ambiguious_frame[1..5].iter().checksum();
             ambiguious_frame[12] = ambiguious_frame[1..12].iter().checksum();
 
+            // a valid short frame is always taken to be one, so the rest of the long frame is corrupted
+            let frames: Vec<RxFrame> = FramedRead::new(&ambiguious_frame[..], WrcBusProtocolCodec::new())
+                .map(|frame| frame.unwrap())
+                .collect().await;
 
+            assert_eq!(frames.len(), 2);
+            assert_eq!(frames[0].id().map(|id| id.cmd), Some(0xaa));
+            assert!(frames[1].is_invalid_checksum());
+            assert_eq!(frames[1].to_bytes(), &ambiguious_frame[7..]);
         }
     }
 
@@ -420,5 +597,41 @@ mod tests {
         let mut buf = BytesMut::new();
         let frame = TxFrame::Short(ShortFrame::new(0x1, 0x2, 0x3, [0x4]));
         codec.encode(frame, &mut buf).expect("encode");
+
+        let frame = LongFrame::new(0x20, 0x84, 0x52, [0x4b, 0x4c, 0x63, 0xf8, 0x81, 0x10, 0x0, 0x6f]);
+        assert_eq!(frame.to_bytes(), [0x32, 0x20, 0x84, 0x52, 0x4b, 0x4c, 0x63, 0xf8, 0x81, 0x10, 0x0, 0x6f, 0x94, 0x34]);
+
+        // the checksum only covers the new frame
+        codec.encode(TxFrame::Long(frame), &mut buf).expect("encode");
+        assert_eq!(&buf[7..], [0x32, 0x20, 0x84, 0x52, 0x4b, 0x4c, 0x63, 0xf8, 0x81, 0x10, 0x0, 0x6f, 0x94, 0x34]);
+    }
+
+    #[test]
+    fn test_codec_corrupted() {
+        let valid_short_frame = [0x32, 0x84, 0xeb, 0xf9, 0x00, 0x96, 0x34];
+        let invalid_short_frame = [0x32, 0x84, 0xeb, 0xf9, 0x00, 0x97, 0x34];
+
+        let mut codec = WrcBusProtocolCodec::new();
+        let mut buf = BytesMut::new();
+        buf.put(&invalid_short_frame[..]);
+        buf.put(&[0x01, 0x02][..]);
+        buf.put(&valid_short_frame[..]);
+
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(frame.is_invalid_checksum());
+        assert_eq!(frame.to_bytes(), invalid_short_frame);
+
+        // skipped bytes are returned before the next frame
+        let skipped = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(matches!(&skipped, RxFrame::Corrupted(data) if data == &[0x01, 0x02]));
+        assert!(!skipped.is_invalid_checksum());
+
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.to_bytes(), valid_short_frame);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        assert_eq!(RxFrame::from_bytes(&valid_short_frame).to_bytes(), valid_short_frame);
+        assert!(RxFrame::from_bytes(&invalid_short_frame).is_invalid_checksum());
+        assert!(matches!(RxFrame::from_bytes(&[0x32, 0x84]), RxFrame::Corrupted(_)));
     }
 }
\ No newline at end of file
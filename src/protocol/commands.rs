@@ -171,9 +171,10 @@ mod cmd_a0 {
         #[packed_field(bits="35")]
         pub unknown_bit35: bool,
 
-
-        // #[packed_field(bytes="4")]
-        // pub unknown_b4: u8,
+        /// Unknown.
+        /// Seems to be always `0x4` when sent from the main WRC.
+        #[packed_field(bits="36:39")]
+        pub unknown_bits36_39: u8,
 
         #[packed_field(bytes="5")]
         pub unknown_byte5: u8,
@@ -257,17 +258,27 @@ impl Temperature {
         }
     }
 
+    /// Temperatures below zero read as 0 (see [Temperature::signed_celsius]).
     pub fn celsius(&self) -> u8 {
         match self.unit {
-            TemperatureUnit::Celsius => self.raw_value - 55,
+            TemperatureUnit::Celsius => self.raw_value.saturating_sub(55),
             TemperatureUnit::Fahrenheit => self.raw_value,
         }
     }
+
+    /// Like [Temperature::celsius], but allows for temperatures below zero
+    /// (e.g., evaporator temperatures).
+    pub fn signed_celsius(&self) -> i8 {
+        match self.unit {
+            TemperatureUnit::Celsius => self.raw_value as i8 - 55,
+            TemperatureUnit::Fahrenheit => self.raw_value as i8,
+        }
+    }
 }
 
 impl Debug for Temperature {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}C", self.celsius())
+        write!(f, "{}C", self.signed_celsius())
     }
 }
 
@@ -419,6 +430,12 @@ mod cmd_52 {
 
         #[packed_field(bits="33")]
         pub clean_up: bool,
+
+        #[packed_field(bits="34..=39")]
+        pub unknown_bits34_39: u8,
+
+        #[packed_field(bytes="5..=7")]
+        pub unknown: [u8; 3],
     }
 
     impl Command for Command52ErvResponse {
@@ -521,24 +538,26 @@ mod cmd_55 {
 pub use cmd_55::{Command55Request, Command55IndoorUnitResponse};
 
 
-// mod cmd_63 {
-//     use super::*;
+mod cmd_63 {
+    use super::*;
 
-//     empty_long_command!(Command63Request, 0x63);
+    empty_long_command!(Command63Request, 0x63);
 
-//     /// Command `0x63`
-//     #[derive(PackedStruct, Debug)]
-//     #[packed_struct(bit_numbering="msb0")]
-//     pub struct Command63Response {
-//         unknown: [u8; 8]
-//     }
+    /// Command `0x63` (IU response)
+    /// 
+    /// Purpose unknown. Polled by both the main and sub WRCs.
+    #[derive(PackedStruct, Debug)]
+    #[packed_struct(bit_numbering="msb0")]
+    pub struct Command63Response {
+        pub unknown: [u8; 8]
+    }
 
-//     impl Command for Command63Response {
-//         const ID: u8 = 0x63;
-//     }
-// }
+    impl Command for Command63Response {
+        const ID: u8 = 0x63;
+    }
+}
 
-// pub use cmd_63::{Command63Request, Command63Response};
+pub use cmd_63::{Command63Request, Command63Response};
 
 
 mod cmd_64 {
@@ -557,7 +576,7 @@ mod cmd_64 {
     }
 
     impl PrecisionTemperature {
-        fn celsius(&self) -> f32 {
+        pub fn celsius(&self) -> f32 {
             let val = ((self.high as i16) << 8) | (self.low as i16);
             return (val - 553) as f32 / 10.0;
         }
@@ -603,7 +622,7 @@ mod cmd_64 {
     }
 }
 
-pub use cmd_64::{Command64Request};
+pub use cmd_64::{Command64Request, PrecisionTemperature, TemperatureProbeSource};
 
 
 
@@ -623,10 +642,18 @@ RX       94: [32, 20, 84, 71, 0, 56, 0, 0, 0, 0, 0, 0, 83, 34]
      b160C  C45E4  77887  C0056
 
  */
-struct EepromLowReadRequest {
+/// Command `0x70` -- EEPROM read, low bytes (request).
+///
+/// Sent from a WRC to an IU to read part of its EEPROM.
+/// Addresses `0x00` and `0x01` both read the option code.
+#[derive(PackedStruct, Debug, Default)]
+#[packed_struct(bit_numbering="msb0")]
+pub struct EepromLowReadRequest {
+    #[packed_field(bytes="0")]
     pub address: u8,
 
     /// Probably empty
+    #[packed_field(bytes="1..=7")]
     pub unknown: [u8; 7]
 }
 
@@ -634,7 +661,10 @@ impl Command for EepromLowReadRequest {
     const ID: u8 = 0x70;
 }
 
-struct EepromLowReadResponse {
+/// Command `0x70` -- EEPROM read, low bytes (response).
+#[derive(PackedStruct, Debug)]
+#[packed_struct(bit_numbering="msb0")]
+pub struct EepromLowReadResponse {
     pub data: [u8; 8]
 }
 
@@ -642,10 +672,15 @@ impl Command for EepromLowReadResponse {
     const ID: u8 = 0x70;
 }
 
-struct EepromHighReadRequest {
+/// Command `0x71` -- EEPROM read, high bytes (request).
+#[derive(PackedStruct, Debug, Default)]
+#[packed_struct(bit_numbering="msb0")]
+pub struct EepromHighReadRequest {
+    #[packed_field(bytes="0")]
     pub address: u8,
 
     /// Probably empty
+    #[packed_field(bytes="1..=7")]
     pub unknown: [u8; 7]
 }
 
@@ -653,7 +688,12 @@ impl Command for EepromHighReadRequest {
     const ID: u8 = 0x71;
 }
 
-struct EepromHighReadResponse {
+/// Command `0x71` -- EEPROM read, high bytes (response).
+///
+/// Only the first 2 bytes seem to be used for the option code.
+#[derive(PackedStruct, Debug)]
+#[packed_struct(bit_numbering="msb0")]
+pub struct EepromHighReadResponse {
     pub data: [u8; 8]
 }
 
@@ -0,0 +1,306 @@
+use std::{fmt::Display, sync::OnceLock};
+
+use packed_struct::PackedStruct;
+
+use super::{codec::{RxFrame, FrameId}, commands::*, iu::IndoorUnitFanSpeed, layout::{self, CommandLayout}};
+
+
+/// A decoded command field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+
+    /// The meaning of the field (or bits) isn't known.
+    pub unknown: bool
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+#[derive(Default)]
+struct Fields(Vec<Field>);
+
+impl Fields {
+    fn value(mut self, name: &str, value: impl Display) -> Self {
+        self.0.push(Field { name: name.to_string(), value: value.to_string(), unknown: false });
+        self
+    }
+
+    fn flag(self, name: &str, value: bool) -> Self {
+        self.value(name, if value { "on" } else { "off" })
+    }
+
+    fn temp(self, name: &str, value: &Temperature) -> Self {
+        self.value(name, format!("{value:?}"))
+    }
+
+    fn fan_speed(self, name: &str, raw: u8) -> Self {
+        match IndoorUnitFanSpeed::from_raw(raw) {
+            Some(fan_speed) => self.value(name, fan_speed.name()),
+            None => self.value(name, raw)
+        }
+    }
+
+    /// Unknown bytes, only listed if any of their bits are set.
+    fn unknown(mut self, name: &str, bytes: &[u8]) -> Self {
+        if bytes.iter().any(|b| *b != 0) {
+            let value: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            self.0.push(Field { name: name.to_string(), value, unknown: true });
+        }
+        self
+    }
+
+    /// Unknown bit, only listed if set.
+    fn unknown_bit(self, name: &str, value: bool) -> Self {
+        self.unknown(name, &[value as u8])
+    }
+}
+
+/// The layouts of the known commands, built once.
+fn layouts() -> &'static [CommandLayout] {
+    static LAYOUTS: OnceLock<Vec<CommandLayout>> = OnceLock::new();
+    LAYOUTS.get_or_init(layout::commands)
+}
+
+/// Decode the fields of a long frame, using the first command layout that matches it.
+///
+/// Also returns the bits not covered by the layout's fields; any of them that are set are listed as unknown.
+fn decode_long(id: FrameId, data: &[u8; 8]) -> Option<(Fields, Vec<u8>)> {
+    let layout = layouts().iter().find(|layout| layout.matches(id.src, id.dst, id.cmd, data.len()))?;
+
+    let fields = Fields::default();
+
+    let decoded = match layout.name {
+        "Command52IndoorUnitResponse" => {
+            let cmd = Command52IndoorUnitResponse::unpack(data).ok()?;
+            fields
+                .temp("setpoint", &cmd.setpoint_temp)
+                .temp("room", &cmd.iu_room_temp)
+                .temp("eva_in", &cmd.iu_eva_inlet_temp)
+                .temp("eva_out", &cmd.iu_eva_outlet_temp)
+                .value("mode", format!("{:?}", cmd.mode))
+                .fan_speed("fan", cmd.fan_speed)
+                .flag("power", cmd.power)
+                .flag("defrost", cmd.defrost)
+                .flag("clean_filter", cmd.clean_filter)
+                .unknown_bit("bit33", cmd.unknown_bit33)
+                .unknown_bit("bit34", cmd.unknown_bit34)
+        },
+
+        "Command52ErvResponse" => {
+            let cmd = Command52ErvResponse::unpack(data).ok()?;
+            fields
+                .flag("power", cmd.power)
+                .flag("clean_up", cmd.clean_up)
+                .unknown("bits34_39", &[cmd.unknown_bits34_39])
+                .unknown("bytes5_7", &cmd.unknown)
+        },
+
+        "Command53IndoorUnitResponse" => {
+            let cmd = Command53IndoorUnitResponse::unpack(data).ok()?;
+            fields.flag("humidifier", cmd.humidifier)
+        },
+
+        "Command54IndoorUnitResponse" => {
+            let cmd = Command54IndoorUnitResponse::unpack(data).ok()?;
+            fields
+                .flag("oa_intake", cmd.oa_intake)
+                .flag("away", cmd.away_mds)
+                .flag("s_plasma_ion", cmd.s_plasma_ion)
+                .flag("range_hood", cmd.range_hood)
+                .flag("discharge_temp_control", cmd.discharge_temp_control)
+                .unknown_bit("bit58", cmd.unknown_bit58)
+                .unknown_bit("bit60", cmd.unknown_bit60)
+        },
+
+        "Command55IndoorUnitResponse" => {
+            let cmd = Command55IndoorUnitResponse::unpack(data).ok()?;
+            fields
+                .value("humidity", cmd.humidity)
+                .value("co2", cmd.co2)
+                .unknown("bytes1_7", &cmd.unknown)
+        },
+
+        "Command63Response" => {
+            let cmd = Command63Response::unpack(data).ok()?;
+            fields.unknown("bytes0_7", &cmd.unknown)
+        },
+
+        "Command64Request" => {
+            let cmd = Command64Request::unpack(data).ok()?;
+            fields
+                .value("sensor", format!("{:?}", cmd.active_temp_sensor))
+                .value("wrc_temp", format!("{:?}", cmd.wrc_temperature))
+                .value("iu_temp", format!("{:?}", cmd.iu_temperature))
+                .unknown("byte0", &[cmd.unknown_b0])
+                .unknown("bits8_14", &[cmd.unknown_b1_8_14])
+                .unknown("byte6", &[cmd.unknown2])
+                .unknown("byte7", &[cmd.unknown3])
+        },
+
+        "EepromLowReadRequest" => {
+            let cmd = EepromLowReadRequest::unpack(data).ok()?;
+            fields.value("address", format!("{:02x}", cmd.address)).unknown("bytes1_7", &cmd.unknown)
+        },
+
+        "EepromHighReadRequest" => {
+            let cmd = EepromHighReadRequest::unpack(data).ok()?;
+            fields.value("address", format!("{:02x}", cmd.address)).unknown("bytes1_7", &cmd.unknown)
+        },
+
+        "EepromLowReadResponse" | "EepromHighReadResponse" => {
+            let value: String = data.iter().map(|b| format!("{b:02x}")).collect();
+            fields.value("data", value)
+        },
+
+        // also the IU's `0x50` ack, echoing the request
+        "CommandA0" => {
+            let cmd = CommandA0::unpack(data).ok()?;
+            fields
+                .value("setpoint", format!("{}C", cmd.setpoint_temp))
+                .value("mode", format!("{:?}", cmd.mode))
+                .fan_speed("fan", cmd.fan_speed)
+                .flag("power", cmd.power)
+                .flag("s_plasma_ion", cmd.s_plasma_ion)
+                .flag("humidifier", cmd.humidifier)
+                .flag("reset_clean_filter", cmd.reset_clean_filter)
+                .unknown("byte0", &[cmd.unknown_byte0])
+                .unknown("byte1", &[cmd.unknown_byte1])
+                .unknown_bit("bit32", cmd.unknown_bit32)
+                .unknown_bit("bit33", cmd.unknown_bit33)
+                .unknown_bit("bit35", cmd.unknown_bit35)
+                .unknown("bits36_39", &[cmd.unknown_bits36_39])
+                .unknown("byte5", &[cmd.unknown_byte5])
+                .unknown("byte6", &[cmd.unknown_byte6])
+                .unknown("byte7", &[cmd.unknown_byte7])
+        },
+
+        "CommandC4Request" => {
+            let cmd = CommandC4Request::unpack(data).ok()?;
+            fields
+                .value("indoor_units", cmd.number_of_indoor_units)
+                .unknown("byte0", &[cmd.unknown_byte0])
+                .unknown("iu_address", &[cmd.unknown_iu_address])
+                .unknown("byte7", &[cmd.unknown_byte7])
+        },
+
+        "CommandC5Request" => {
+            let cmd = CommandC5Request::unpack(data).ok()?;
+            fields
+                .unknown("byte0", &[cmd.unknown_byte0])
+                .unknown("byte1", &[cmd.unknown_byte1])
+                .unknown("bytes2_7", &cmd.unknown)
+        },
+
+        "CommandC5Response" => {
+            let cmd = CommandC5Response::unpack(data).ok()?;
+            fields.unknown("byte0", &[cmd.unknown_byte0]).unknown("bytes1_7", &cmd.unknown)
+        },
+
+        "CommandC6" => {
+            let cmd = CommandC6::unpack(data).ok()?;
+            fields
+                .value("indoor_units", cmd.number_of_indoor_units)
+                .unknown("byte0", &[cmd.unknown_byte0])
+                .unknown("bytes2_7", &cmd.unknown)
+        },
+
+        "TrackingCommandFA" => {
+            let cmd = TrackingCommandFA::unpack(data).ok()?;
+            fields.unknown("byte0", &[cmd.unknown_b0]).unknown("bytes1_7", &cmd.unknown)
+        },
+
+        "TrackingCommandFB" => {
+            let cmd = TrackingCommandFB::unpack(data).ok()?;
+            fields.value("state", format!("{:?}", cmd.state)).unknown("bytes1_7", &cmd.unknown)
+        },
+
+        // commands without fields (e.g. requests and `0xd1`), any set bits are unknown
+        _ => fields
+    };
+
+    Some((decoded, layout.uncovered_bits()))
+}
+
+fn decode_short(id: FrameId, data: &[u8; 1]) -> Option<Fields> {
+    let fields = Fields::default();
+
+    let decoded = match id.cmd {
+        TrackingHello::ID => {
+            let cmd = TrackingHello::unpack(data).ok()?;
+            fields.unknown("byte0", &[cmd.unknown])
+        },
+
+        TrackingAssignAddress::ID => {
+            let cmd = TrackingAssignAddress::unpack(data).ok()?;
+            fields.value("address", format!("{:02x}", cmd.address))
+        },
+
+        TrackingAddressAssigned::ID => {
+            let cmd = TrackingAddressAssigned::unpack(data).ok()?;
+            fields.value("address", format!("{:02x}", cmd.address))
+        },
+
+        _ => return None
+    };
+
+    Some(decoded)
+}
+
+/// Decode a frame into the fields of its command.
+///
+/// Set bits that aren't covered by a known field are listed as unknown fields
+/// (named after their byte, with the known bits masked out).
+///
+/// Returns `None` for unknown commands (and corrupted frames).
+pub fn decode(frame: &RxFrame) -> Option<Vec<Field>> {
+    match frame {
+        RxFrame::Long(frame) => {
+            let (mut fields, uncovered) = decode_long(frame.id, &frame.data)?;
+
+            for (i, (byte, uncovered)) in frame.data.iter().zip(uncovered).enumerate() {
+                fields = fields.unknown(&format!("byte{i}"), &[byte & uncovered]);
+            }
+
+            Some(fields.0)
+        },
+        RxFrame::Short(frame) => Some(decode_short(frame.id, &frame.data)?.0),
+        RxFrame::Corrupted(_) => None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{codec::LongFrame, addresses::*};
+
+    use super::*;
+
+    fn decoded(src: u8, dst: u8, cmd: u8, data: [u8; 8]) -> Option<String> {
+        let fields = decode(&RxFrame::Long(LongFrame::new(src, dst, cmd, data)))?;
+        Some(fields.iter()
+            .map(|field| if field.unknown { format!("!{field}") } else { field.to_string() })
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decoded(0x20, MAIN_WRC, 0x52, [0x4a, 0x4c, 0x4e, 0xf8, 0x22, 0x10, 0x00, 0x4e]).unwrap(),
+            "setpoint=19C room=21C eva_in=23C eva_out=23C mode=Cool fan=auto power=off defrost=off clean_filter=on !bit34=01 !byte3=f8"
+        );
+
+        assert_eq!(
+            decoded(0x20, MAIN_WRC, 0x50, [0x1f, 0x18, 0x18, 0x01, 0xf4, 0x00, 0x00, 0x00]).unwrap(),
+            "setpoint=24C mode=Cool fan=auto power=on s_plasma_ion=off humidifier=off reset_clean_filter=off !byte0=1f !byte1=18 !bit32=01 !bit33=01 !bit35=01 !bits36_39=04"
+        );
+
+        assert_eq!(decoded(MAIN_WRC, 0x20, 0x52, [0x00; 8]).unwrap(), "");
+        assert_eq!(decoded(MAIN_WRC, 0x20, 0x83, [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), None);
+    }
+}
@@ -1,6 +1,13 @@
+use std::fmt::Display;
 
-#[derive(Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+use super::commands::{Command52IndoorUnitResponse, Command53IndoorUnitResponse, Command54IndoorUnitResponse, Command55IndoorUnitResponse, Command64Request, CommandA0};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum IndoorUnitMode {
+    #[default]
     Auto,
     Heat,
     Cool,
@@ -8,14 +15,139 @@ pub enum IndoorUnitMode {
     Fan
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum IndoorUnitFanSpeed {
+    #[default]
     Auto,
     Low,
     Medium,
     High
 }
 
+impl IndoorUnitMode {
+    pub const ALL: [Self; 5] = [Self::Auto, Self::Heat, Self::Cool, Self::Dry, Self::Fan];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Heat => "heat",
+            Self::Cool => "cool",
+            Self::Dry => "dry",
+            Self::Fan => "fan",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl IndoorUnitFanSpeed {
+    pub const ALL: [Self; 4] = [Self::Auto, Self::Low, Self::Medium, Self::High];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|fan_speed| fan_speed.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Decode the 3-bit fan speed field used by commands `0xa0` and `0x52`.
+    pub fn from_raw(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Auto),
+            2 => Some(Self::Low),
+            4 => Some(Self::Medium),
+            5 => Some(Self::High),
+            _ => None
+        }
+    }
+
+    /// Encode as the 3-bit fan speed field used by commands `0xa0` and `0x52`.
+    pub fn raw(&self) -> u8 {
+        match self {
+            Self::Auto => 0,
+            Self::Low => 2,
+            Self::Medium => 4,
+            Self::High => 5,
+        }
+    }
+}
+
+/// A field of [IndoorUnitState].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IndoorUnitField {
+    Power,
+    Mode,
+    FanSpeed,
+    SetpointTemp,
+    RoomTemp,
+    IuSensorTemp,
+    WrcSensorTemp,
+    EvaInletTemp,
+    EvaOutletTemp,
+    Humidity,
+    Co2,
+    Defrost,
+    SPlasmaIon,
+    CleanFilter,
+    Humidifier,
+    OaIntake,
+    AwayMds,
+}
+
+impl IndoorUnitField {
+    pub const ALL: [Self; 17] = [
+        Self::Power, Self::Mode, Self::FanSpeed, Self::SetpointTemp,
+        Self::RoomTemp, Self::IuSensorTemp, Self::WrcSensorTemp, Self::EvaInletTemp, Self::EvaOutletTemp,
+        Self::Humidity, Self::Co2,
+        Self::Defrost, Self::SPlasmaIon, Self::CleanFilter, Self::Humidifier, Self::OaIntake, Self::AwayMds,
+    ];
+
+    /// Short name of the field (e.g., for logging).
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Power => "power",
+            Self::Mode => "mode",
+            Self::FanSpeed => "fan_speed",
+            Self::SetpointTemp => "setpoint_temp",
+            Self::RoomTemp => "room_temp",
+            Self::IuSensorTemp => "iu_sensor_temp",
+            Self::WrcSensorTemp => "wrc_sensor_temp",
+            Self::EvaInletTemp => "eva_inlet_temp",
+            Self::EvaOutletTemp => "eva_outlet_temp",
+            Self::Humidity => "humidity",
+            Self::Co2 => "co2",
+            Self::Defrost => "defrost",
+            Self::SPlasmaIon => "s_plasma_ion",
+            Self::CleanFilter => "clean_filter",
+            Self::Humidifier => "humidifier",
+            Self::OaIntake => "oa_intake",
+            Self::AwayMds => "away_mds",
+        }
+    }
+}
+
+/// The value of an [IndoorUnitField].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndoorUnitValue {
+    Bool(bool),
+    Mode(IndoorUnitMode),
+    FanSpeed(IndoorUnitFanSpeed),
+    Temperature(i8),
+    PrecisionTemperature(f32),
+    Raw(u8),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct IndoorUnitState {
     pub power: bool,
 
@@ -25,6 +157,27 @@ pub struct IndoorUnitState {
 
     pub setpoint_temp: u8,
 
+    /// Room temperature, as reported by the IU in `0x52` (whole degrees).
+    pub room_temp: i8,
+
+    /// Temperature measured by the IU's own sensor, as reported in `0x64`.
+    pub iu_sensor_temp: f32,
+
+    /// Temperature measured by the WRC's sensor, as echoed by the IU in `0x64`.
+    pub wrc_sensor_temp: f32,
+
+    /// Evaporator inlet temperature (WRC service menu: 3,2)
+    pub eva_inlet_temp: i8,
+
+    /// Evaporator outlet temperature (WRC service menu: 3,3)
+    pub eva_outlet_temp: i8,
+
+    /// Humidity, as reported in `0x55` (raw value, scale unknown).
+    pub humidity: u8,
+
+    /// CO2 level, as reported in `0x55` (raw value, scale unknown).
+    pub co2: u8,
+
     pub defrost: bool,
 
     pub s_plasma_ion: bool,
@@ -36,4 +189,187 @@ pub struct IndoorUnitState {
     pub oa_intake: bool,
 
     pub away_mds: bool,
-}
\ No newline at end of file
+}
+
+impl IndoorUnitState {
+    /// Update from an IU's reply to command `0x52`.
+    pub fn update_from_52(&mut self, resp: &Command52IndoorUnitResponse) {
+        self.power = resp.power;
+        self.mode = resp.mode.into();
+        self.setpoint_temp = resp.setpoint_temp.celsius();
+        self.room_temp = resp.iu_room_temp.signed_celsius();
+        self.eva_inlet_temp = resp.iu_eva_inlet_temp.signed_celsius();
+        self.eva_outlet_temp = resp.iu_eva_outlet_temp.signed_celsius();
+        self.defrost = resp.defrost;
+        self.clean_filter = resp.clean_filter;
+
+        if let Some(fan_speed) = IndoorUnitFanSpeed::from_raw(resp.fan_speed) {
+            self.fan_speed = fan_speed;
+        }
+    }
+
+    /// Update from an IU's reply to command `0x53`.
+    pub fn update_from_53(&mut self, resp: &Command53IndoorUnitResponse) {
+        self.humidifier = resp.humidifier;
+    }
+
+    /// Update from an IU's reply to command `0x54`.
+    pub fn update_from_54(&mut self, resp: &Command54IndoorUnitResponse) {
+        self.oa_intake = resp.oa_intake;
+        self.away_mds = resp.away_mds;
+        self.s_plasma_ion = resp.s_plasma_ion;
+    }
+
+    /// Update from an IU's reply to command `0x55`.
+    pub fn update_from_55(&mut self, resp: &Command55IndoorUnitResponse) {
+        self.humidity = resp.humidity;
+        self.co2 = resp.co2;
+    }
+
+    /// Update from an IU's reply to command `0x64`.
+    pub fn update_from_64(&mut self, resp: &Command64Request) {
+        self.iu_sensor_temp = resp.iu_temperature.celsius();
+        self.wrc_sensor_temp = resp.wrc_temperature.celsius();
+    }
+
+    /// Update from an IU's settings change ack (command `0x50`, which echoes the `0xa0` request).
+    pub fn update_from_a0(&mut self, settings: &CommandA0) {
+        self.power = settings.power;
+        self.mode = settings.mode.into();
+        self.setpoint_temp = settings.setpoint_temp;
+        self.s_plasma_ion = settings.s_plasma_ion;
+        self.humidifier = settings.humidifier;
+
+        if let Some(fan_speed) = IndoorUnitFanSpeed::from_raw(settings.fan_speed) {
+            self.fan_speed = fan_speed;
+        }
+    }
+
+    /// Build a settings change request (`0xa0`) that applies this state.
+    ///
+    /// Fields of unknown purpose are set as seen from a physical main WRC.
+    pub fn to_a0(&self) -> CommandA0 {
+        CommandA0 {
+            unknown_byte0: 0x1f,
+            unknown_byte1: 0x18,
+            fan_speed: self.fan_speed.raw(),
+            setpoint_temp: self.setpoint_temp,
+            s_plasma_ion: self.s_plasma_ion,
+            reset_clean_filter: false,
+            humidifier: self.humidifier,
+            mode: self.mode.into(),
+            unknown_bit32: true,
+            unknown_bit33: true,
+            power: self.power,
+            unknown_bit35: self.power,
+            unknown_bits36_39: 0x4,
+            unknown_byte5: 0,
+            unknown_byte6: 0,
+            unknown_byte7: 0,
+        }
+    }
+
+    /// Get the value of a field.
+    pub fn get(&self, field: IndoorUnitField) -> IndoorUnitValue {
+        use IndoorUnitValue::*;
+
+        match field {
+            IndoorUnitField::Power => Bool(self.power),
+            IndoorUnitField::Mode => Mode(self.mode),
+            IndoorUnitField::FanSpeed => FanSpeed(self.fan_speed),
+            IndoorUnitField::SetpointTemp => Temperature(self.setpoint_temp as i8),
+            IndoorUnitField::RoomTemp => Temperature(self.room_temp),
+            IndoorUnitField::IuSensorTemp => PrecisionTemperature(self.iu_sensor_temp),
+            IndoorUnitField::WrcSensorTemp => PrecisionTemperature(self.wrc_sensor_temp),
+            IndoorUnitField::EvaInletTemp => Temperature(self.eva_inlet_temp),
+            IndoorUnitField::EvaOutletTemp => Temperature(self.eva_outlet_temp),
+            IndoorUnitField::Humidity => Raw(self.humidity),
+            IndoorUnitField::Co2 => Raw(self.co2),
+            IndoorUnitField::Defrost => Bool(self.defrost),
+            IndoorUnitField::SPlasmaIon => Bool(self.s_plasma_ion),
+            IndoorUnitField::CleanFilter => Bool(self.clean_filter),
+            IndoorUnitField::Humidifier => Bool(self.humidifier),
+            IndoorUnitField::OaIntake => Bool(self.oa_intake),
+            IndoorUnitField::AwayMds => Bool(self.away_mds),
+        }
+    }
+}
+
+
+/// An IU's option code, as read from its EEPROM with commands `0x70` and `0x71`.
+///
+/// The option code configures which features an installed IU has.
+/// How it maps onto the code printed on the IU (e.g., `01606C-1C544E-27788C-370065`) isn't known yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OptionCode(pub [u8; 10]);
+
+impl Display for OptionCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for b in self.0 {
+            write!(f, "{b:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The features an IU supports.
+///
+/// Features are `Some(true)` once confirmed, and `None` while unknown, in which case they're assumed
+/// to be supported (so their controls aren't hidden from the user). The `0x53`/`0x54` feature bits are
+/// the features' on/off state, so a set bit confirms a feature, but a clear bit only means it's off.
+/// How the option code encodes the installed features isn't known yet, so nothing is detected as unsupported.
+///
+/// Mode, fan speed and swing/blade support aren't detected at all: no reply is known to carry them, and
+/// every IU is offered all modes and fan speeds (and no swing control).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndoorUnitCapabilities {
+    pub humidifier: Option<bool>,
+
+    pub s_plasma_ion: Option<bool>,
+
+    pub oa_intake: Option<bool>,
+
+    pub away_mds: Option<bool>,
+
+    pub range_hood: Option<bool>,
+
+    pub discharge_temp_control: Option<bool>,
+
+    /// The IU answers `0x55` polls (humidity and CO2).
+    pub humidity_co2: bool,
+
+    pub option_code: Option<OptionCode>,
+}
+
+impl Default for IndoorUnitCapabilities {
+    fn default() -> Self {
+        Self {
+            humidifier: None,
+            s_plasma_ion: None,
+            oa_intake: None,
+            away_mds: None,
+            range_hood: None,
+            discharge_temp_control: None,
+            humidity_co2: false,
+            option_code: None,
+        }
+    }
+}
+
+impl IndoorUnitCapabilities {
+    /// Does the IU have (or might it have) the feature behind a field of its state?
+    pub fn supports(&self, field: IndoorUnitField) -> bool {
+        let feature = match field {
+            IndoorUnitField::Humidifier => self.humidifier,
+            IndoorUnitField::SPlasmaIon => self.s_plasma_ion,
+            IndoorUnitField::OaIntake => self.oa_intake,
+            IndoorUnitField::AwayMds => self.away_mds,
+            IndoorUnitField::Humidity | IndoorUnitField::Co2 => return self.humidity_co2,
+            _ => None
+        };
+
+        feature != Some(false)
+    }
+}
@@ -0,0 +1,291 @@
+use std::ops::RangeInclusive;
+
+use packed_struct::{PackedStruct, PackedStructSlice, PackingError, types::bits::ByteArray, debug_fmt::{PackedStructDebug, PackedStructDisplay}};
+
+use super::{commands::*, addresses::*};
+
+
+/// How a field's value is interpreted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldKind {
+    Bool,
+    Uint,
+
+    /// [Temperature]
+    Temperature,
+
+    /// [PrecisionTemperature]
+    PrecisionTemperature,
+
+    /// Raw values and the names of the variants they represent.
+    Enum(Vec<(u64, String)>)
+}
+
+/// The position of a field within a command's data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldLayout {
+    /// Field name, e.g. `setpoint_temp`, or `unknown[2]` for array elements.
+    pub name: String,
+
+    /// Bit positions (msb0, i.e. bit 0 is the MSB of the first data byte).
+    pub bits: RangeInclusive<usize>,
+
+    pub kind: FieldKind,
+
+    /// The meaning of the field isn't known.
+    pub unknown: bool
+}
+
+/// The field layout of a command, and the frames it applies to.
+#[derive(Clone, Debug)]
+pub struct CommandLayout {
+    /// Name of the command struct, e.g. `CommandA0`.
+    pub name: &'static str,
+
+    pub cmd: u8,
+    pub src: RangeInclusive<u8>,
+    pub dst: RangeInclusive<u8>,
+
+    /// Data size in bytes.
+    pub size: usize,
+
+    pub fields: Vec<FieldLayout>,
+
+    /// Unpack data as the command struct (running its checks) and pack it again.
+    pub roundtrip: fn(&[u8]) -> Result<Vec<u8>, PackingError>
+}
+
+impl CommandLayout {
+    /// Does the layout apply to a frame?
+    pub fn matches(&self, src: u8, dst: u8, cmd: u8, size: usize) -> bool {
+        self.cmd == cmd && self.size == size && self.src.contains(&src) && self.dst.contains(&dst)
+    }
+
+    /// Bits of each data byte that aren't covered by a field.
+    pub fn uncovered_bits(&self) -> Vec<u8> {
+        let mut uncovered = vec![0xff; self.size];
+
+        for field in &self.fields {
+            for bit in field.bits.clone() {
+                uncovered[bit / 8] &= !(0x80 >> (bit % 8));
+            }
+        }
+
+        uncovered
+    }
+}
+
+/// The fields of a packed struct, as (name, bits, debug value), from its `Display` impl.
+fn packed_fields<T: PackedStruct + PackedStructDebug>(value: &T) -> Vec<(String, RangeInclusive<usize>, String)> {
+    let display = PackedStructDisplay {
+        packed_struct: value,
+        header: false,
+        raw_decimal: false,
+        raw_hex: false,
+        raw_binary: false,
+        fields: true
+    };
+
+    // e.g. `  setpoint_temp | bits  19:23  | 0b00000    | "0"`
+    display.to_string().lines()
+        .filter_map(|line| {
+            let mut columns = line.split('|').map(str::trim);
+            let name = columns.next()?;
+            let (start, end) = columns.next()?.strip_prefix("bits")?.trim().split_once(':')?;
+            let value = columns.nth(1)?;
+
+            Some((
+                name.to_string(),
+                start.trim().parse().ok()?..=end.trim().parse().ok()?,
+                value.trim_matches('"').to_string()
+            ))
+        })
+        .collect()
+}
+
+/// Set the bits of a field (msb0) to `value`.
+fn set_bits(data: &mut [u8], bits: &RangeInclusive<usize>, value: u64) {
+    let width = bits.end() - bits.start() + 1;
+
+    for (i, bit) in bits.clone().enumerate() {
+        let mask = 0x80 >> (bit % 8);
+
+        if value & (1 << (width - 1 - i)) != 0 {
+            data[bit / 8] |= mask;
+        } else {
+            data[bit / 8] &= !mask;
+        }
+    }
+}
+
+/// The field layout of a packed struct, read from the layout generated by its `PackedStruct` derive
+/// so it can't drift from the struct definition.
+///
+/// Field kinds are inferred from their values. The variants of enums are found by
+/// unpacking every possible raw value.
+pub fn fields<T: PackedStruct + PackedStructDebug>() -> Vec<FieldLayout> {
+    let zeroed = T::ByteArray::new(0);
+    let Ok(value) = T::unpack(&zeroed) else {
+        panic!("{} can't be unpacked from zeros", std::any::type_name::<T>());
+    };
+
+    packed_fields(&value).into_iter()
+        // padding, e.g. in empty commands
+        .filter(|(name, _, _)| !name.starts_with('_'))
+        .map(|(name, bits, value)| {
+            let kind = if value == "true" || value == "false" {
+                FieldKind::Bool
+            } else if value.parse::<i64>().is_ok() {
+                FieldKind::Uint
+            } else if let Some(temp) = value.strip_suffix('C').filter(|temp| temp.parse::<f32>().is_ok()) {
+                if temp.contains('.') { FieldKind::PrecisionTemperature } else { FieldKind::Temperature }
+            } else {
+                let width = bits.end() - bits.start() + 1;
+                assert!(width <= 16, "enum field {name} is too wide");
+
+                let variants = (0..1_u64 << width)
+                    .filter_map(|raw| {
+                        let mut data = zeroed.clone();
+                        set_bits(data.as_mut_bytes_slice(), &bits, raw);
+
+                        let value = T::unpack(&data).ok()?;
+                        let (_, _, variant) = packed_fields(&value).into_iter().find(|(n, _, _)| *n == name)?;
+
+                        Some((raw, variant))
+                    })
+                    .collect();
+
+                FieldKind::Enum(variants)
+            };
+
+            FieldLayout {
+                unknown: name.starts_with("unknown"),
+                name,
+                bits,
+                kind
+            }
+        })
+        .collect()
+}
+
+fn roundtrip<T: PackedStruct + Command>(data: &[u8]) -> Result<Vec<u8>, PackingError> {
+    let command = T::unpack_from_slice(data)?;
+    command.check();
+
+    Ok(command.pack()?.as_bytes_slice().to_vec())
+}
+
+fn command<T: PackedStruct + PackedStructDebug + Command>(src: RangeInclusive<u8>, dst: RangeInclusive<u8>) -> CommandLayout {
+    let name = std::any::type_name::<T>();
+
+    CommandLayout {
+        name: name.rsplit("::").next().unwrap_or(name),
+        cmd: T::ID,
+        src,
+        dst,
+        size: <T::ByteArray as ByteArray>::len(),
+        fields: fields::<T>(),
+        roundtrip: roundtrip::<T>
+    }
+}
+
+/// The layouts of the known commands. The first layout that matches a frame applies
+/// (e.g. when [super::decode::decode] picks how to decode it).
+pub fn commands() -> Vec<CommandLayout> {
+    const WRC: RangeInclusive<u8> = MAIN_WRC..=SUB_WRC;
+    const IU: RangeInclusive<u8> = INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST;
+    const ERV: RangeInclusive<u8> = ERV_FIRST..=ERV_LAST;
+    const UNIT: RangeInclusive<u8> = INDOOR_UNIT_FIRST..=ERV_LAST;
+    const ANY: RangeInclusive<u8> = 0x00..=0xff;
+
+    vec![
+        command::<Command52IndoorUnitResponse>(IU, WRC),
+        command::<Command52ErvResponse>(ERV, WRC),
+        command::<Command53IndoorUnitResponse>(IU, WRC),
+        command::<Command54IndoorUnitResponse>(IU, WRC),
+        command::<Command55IndoorUnitResponse>(IU, WRC),
+        command::<Command63Response>(IU, WRC),
+
+        command::<Command52Request>(WRC, UNIT),
+        command::<Command53Request>(WRC, UNIT),
+        command::<Command54Request>(WRC, UNIT),
+        command::<Command55Request>(WRC, UNIT),
+        command::<Command63Request>(WRC, UNIT),
+
+        // the IU replies with the request, filling in its own sensor temperature
+        command::<Command64Request>(WRC, IU),
+        command::<Command64Request>(IU, WRC),
+
+        command::<EepromLowReadRequest>(WRC, IU),
+        command::<EepromHighReadRequest>(WRC, IU),
+        command::<EepromLowReadResponse>(IU, WRC),
+        command::<EepromHighReadResponse>(IU, WRC),
+
+        command::<CommandA0>(WRC, IU),
+        // `0x50` is the IU's ack, echoing the `0xa0` request
+        CommandLayout { cmd: 0x50, ..command::<CommandA0>(IU, WRC) },
+
+        command::<CommandC4Request>(MAIN_WRC..=MAIN_WRC, SUB_WRC..=SUB_WRC),
+        command::<CommandC4Request>(MAIN_WRC..=MAIN_WRC, ENUMERATION_BROADCAST..=ENUMERATION_BROADCAST),
+        command::<CommandC5Request>(MAIN_WRC..=MAIN_WRC, SUB_WRC..=SUB_WRC),
+        command::<CommandC5Response>(SUB_WRC..=SUB_WRC, MAIN_WRC..=MAIN_WRC),
+        command::<CommandC6>(MAIN_WRC..=MAIN_WRC, ENUMERATION_BROADCAST..=ENUMERATION_BROADCAST),
+        command::<CommandC4Reply>(SUB_WRC..=SUB_WRC, MAIN_WRC..=MAIN_WRC),
+        command::<CommandD1Response>(SUB_WRC..=SUB_WRC, MAIN_WRC..=MAIN_WRC),
+        command::<CommandD1>(ANY, ANY),
+
+        // tracking frames are sent from temporary addresses
+        command::<TrackingCommandFA>(ANY, ANY),
+        command::<TrackingCommandFB>(ANY, ANY),
+        command::<TrackingHello>(ANY, ANY),
+        command::<TrackingAssignAddress>(ANY, ANY),
+        command::<TrackingAddressAssigned>(ANY, ANY),
+    ]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_layouts() {
+        let commands = commands();
+
+        let a0 = commands.iter().find(|command| command.name == "CommandA0").unwrap();
+        assert_eq!((a0.cmd, a0.size), (0xa0, 8));
+
+        let field = |command: &CommandLayout, name: &str| command.fields.iter().find(|field| field.name == name).unwrap().clone();
+
+        assert_eq!(field(a0, "setpoint_temp"), FieldLayout { name: "setpoint_temp".to_string(), bits: 19..=23, kind: FieldKind::Uint, unknown: false });
+        assert_eq!(field(a0, "power").kind, FieldKind::Bool);
+        assert!(field(a0, "unknown_byte0").unknown);
+
+        let FieldKind::Enum(modes) = field(a0, "mode").kind else { panic!("expected an enum") };
+        assert_eq!(modes.iter().map(|(raw, name)| format!("{raw}={name}")).collect::<Vec<_>>(), ["0=Auto", "1=Cool", "2=Dry", "3=Fan", "4=Heat"]);
+
+        // bits 24 and 28 aren't mapped
+        assert_eq!(a0.uncovered_bits(), [0, 0, 0, 0x88, 0, 0, 0, 0]);
+
+        let iu52 = &commands[0];
+        assert_eq!(field(iu52, "iu_room_temp").kind, FieldKind::Temperature);
+        assert_eq!(field(iu52, "iu_room_temp").bits, 8..=15);
+
+        let cmd64 = commands.iter().find(|command| command.name == "Command64Request").unwrap();
+        assert_eq!(field(cmd64, "wrc_temperature").kind, FieldKind::PrecisionTemperature);
+        assert_eq!(field(cmd64, "unknown_b1_8_14").bits, 8..=14);
+
+        assert!(commands.iter().any(|command| command.cmd == 0x50 && command.name == "CommandA0"));
+
+        let empty = commands.iter().find(|command| command.name == "Command52Request").unwrap();
+        assert!(empty.fields.is_empty());
+
+        let hello = commands.iter().find(|command| command.name == "TrackingHello").unwrap();
+        assert_eq!(hello.size, 1);
+        assert_eq!(field(hello, "unknown").bits, 0..=7);
+
+        for command in &commands {
+            assert!(command.fields.iter().all(|field| *field.bits.end() < command.size * 8), "{}", command.name);
+        }
+    }
+}
@@ -3,6 +3,8 @@ pub mod codec;
 pub mod commands;
 pub mod addresses;
 pub mod iu;
+pub mod decode;
+pub mod layout;
 
 // pub use codec::*;
 
@@ -0,0 +1,277 @@
+use std::{pin::Pin, task::{Context, Poll, Waker, ready}, time::Duration, future::Future};
+
+use futures::{Stream, Sink, StreamExt, SinkExt};
+use tokio::{sync::broadcast, time::sleep};
+use url::Url;
+use anyhow::Result;
+
+use crate::{config::{Port, PortStream}, protocol::codec::{RxFrame, TxFrame}};
+
+
+/// Connection state changes of a [ReconnectingPort].
+#[derive(Clone, Debug)]
+pub enum ConnectionState {
+    /// The port has been (re-)opened.
+    Connected,
+
+    /// The port was closed or failed.
+    Disconnected {
+        reason: String
+    },
+
+    /// The port will be re-opened after `delay`.
+    Reconnecting {
+        attempt: u32,
+        delay: Duration
+    }
+}
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Copy, Clone, Debug)]
+pub struct Backoff {
+    /// Delay before the first reconnection attempt.
+    pub initial: Duration,
+
+    /// Upper limit of the delay between attempts.
+    pub max: Duration
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(60)
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before reconnection attempt number `attempt` (0-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max)
+    }
+}
+
+
+type ConnectFuture = Pin<Box<dyn Future<Output = Result<Box<dyn PortStream>>> + Send>>;
+
+enum Inner {
+    Connected(Box<dyn PortStream>),
+    Connecting(ConnectFuture)
+}
+
+/// A port that re-opens its URL whenever the underlying port ends or fails.
+///
+/// Frames sent while the port is disconnected are discarded -- a stale frame
+/// delivered after reconnecting would be meaningless to the bus.
+pub struct ReconnectingPort {
+    url: Url,
+    backoff: Backoff,
+
+    inner: Inner,
+    attempt: u32,
+
+    /// Waker of the last `poll_next` that returned `Pending`, so that a
+    /// disconnect detected on the sink side can restart the stream side.
+    rx_waker: Option<Waker>,
+
+    events: broadcast::Sender<ConnectionState>
+}
+
+impl ReconnectingPort {
+    /// Open the port at `url`.
+    ///
+    /// The initial connection attempt is not retried, so that configuration errors are reported immediately.
+    pub async fn open(url: Url, backoff: Backoff) -> Result<Self> {
+        let port = Port::open(&url).await?.framed();
+
+        let (events, _) = broadcast::channel(16);
+
+        Ok(Self {
+            url,
+            backoff,
+            inner: Inner::Connected(port),
+            attempt: 0,
+            rx_waker: None,
+            events
+        })
+    }
+
+    /// Subscribe to connection state changes.
+    pub fn subscribe(&self) -> broadcast::Receiver<ConnectionState> {
+        self.events.subscribe()
+    }
+
+    fn disconnected(&mut self, reason: String) {
+        let attempt = self.attempt;
+        let delay = self.backoff.delay(attempt);
+        self.attempt += 1;
+
+        let _ = self.events.send(ConnectionState::Disconnected { reason });
+        let _ = self.events.send(ConnectionState::Reconnecting { attempt, delay });
+
+        let url = self.url.clone();
+
+        self.inner = Inner::Connecting(Box::pin(async move {
+            sleep(delay).await;
+
+            Ok(Port::open(&url).await?.framed())
+        }));
+
+        if let Some(waker) = self.rx_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Stream for ReconnectingPort {
+    type Item = std::io::Result<RxFrame>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        this.rx_waker = Some(cx.waker().clone());
+
+        loop {
+            match &mut this.inner {
+                Inner::Connected(port) => {
+                    match ready!(port.poll_next_unpin(cx)) {
+                        Some(Ok(frame)) => return Poll::Ready(Some(Ok(frame))),
+                        Some(Err(err)) => this.disconnected(err.to_string()),
+                        None => this.disconnected("port closed".to_string()),
+                    }
+                },
+                Inner::Connecting(connect) => {
+                    match ready!(connect.as_mut().poll(cx)) {
+                        Ok(port) => {
+                            this.attempt = 0;
+                            this.inner = Inner::Connected(port);
+
+                            let _ = this.events.send(ConnectionState::Connected);
+                        },
+                        Err(err) => this.disconnected(format!("{err:#}")),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Sink<TxFrame> for ReconnectingPort {
+    type Error = std::io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+
+        if let Inner::Connected(port) = &mut this.inner {
+            if let Err(err) = ready!(port.poll_ready_unpin(cx)) {
+                this.disconnected(err.to_string());
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: TxFrame) -> Result<(), Self::Error> {
+        let this = &mut *self;
+
+        match &mut this.inner {
+            Inner::Connected(port) => {
+                if let Err(err) = port.start_send_unpin(frame) {
+                    this.disconnected(err.to_string());
+                }
+            },
+            Inner::Connecting(_) => {
+                println!("port {} disconnected, discarding frame: {frame:x?}", this.url);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+
+        if let Inner::Connected(port) = &mut this.inner {
+            if let Err(err) = ready!(port.poll_flush_unpin(cx)) {
+                this.disconnected(err.to_string());
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+
+        match &mut this.inner {
+            Inner::Connected(port) => port.poll_close_unpin(cx),
+            Inner::Connecting(_) => Poll::Ready(Ok(()))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use tokio::{net::TcpListener, io::AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1)
+        };
+
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(1), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(800));
+        assert_eq!(backoff.delay(4), Duration::from_secs(1));
+        assert_eq!(backoff.delay(100), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_close() {
+        let valid_short_frame = [0x32, 0x84, 0xeb, 0xf9, 0x00, 0x96, 0x34];
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("tcp+raw://{}", listener.local_addr().unwrap())).unwrap();
+
+        let backoff = Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(100)
+        };
+
+        let (mut port, (mut socket, _)) = tokio::join!(
+            async { ReconnectingPort::open(url, backoff).await.unwrap() },
+            async { listener.accept().await.unwrap() }
+        );
+
+        let mut events = port.subscribe();
+
+        socket.write_all(&valid_short_frame).await.unwrap();
+        assert!(port.try_next().await.unwrap().is_some());
+
+        // drop the connection -- the port should reconnect and continue
+        drop(socket);
+
+        let (frame, _) = tokio::join!(
+            port.try_next(),
+            async {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                socket.write_all(&valid_short_frame).await.unwrap();
+                socket
+            }
+        );
+        assert!(frame.unwrap().is_some());
+
+        assert!(matches!(events.recv().await.unwrap(), ConnectionState::Disconnected { .. }));
+        assert!(matches!(events.recv().await.unwrap(), ConnectionState::Reconnecting { attempt: 0, .. }));
+        assert!(matches!(events.recv().await.unwrap(), ConnectionState::Connected));
+    }
+}
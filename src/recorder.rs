@@ -0,0 +1,323 @@
+use std::{collections::VecDeque, fs::File, io::{BufWriter, Write}, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use anyhow::{Result, Context};
+use serde_json::{json, Value};
+
+use crate::protocol::{codec::RxFrame, addresses::*, commands::{Command, CommandD1}};
+
+
+/// A frame, and when it was received relative to the start of the recording.
+#[derive(Clone, Debug)]
+pub struct RecordedFrame {
+    /// Microseconds since the start of the recording, from a monotonic clock.
+    pub elapsed_us: u64,
+
+    pub frame: RxFrame
+}
+
+/// A frame that causes a ring buffer recording to be written out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// A frame with an invalid checksum.
+    ChecksumError,
+
+    /// Bytes skipped to find the start of a frame.
+    Resync,
+
+    /// A `0xd1` other than the main WRC's end of phase broadcast (e.g. the sub WRC's bus reset).
+    Reset
+}
+
+impl Trigger {
+    pub fn matches(&self, frame: &RxFrame) -> bool {
+        match (self, frame.id()) {
+            (Trigger::ChecksumError, None) => frame.is_invalid_checksum(),
+            (Trigger::Resync, None) => !frame.is_invalid_checksum(),
+            (Trigger::Reset, Some(id)) => id.cmd == CommandD1::ID && (id.src, id.dst) != (MAIN_WRC, BROADCAST),
+            _ => false
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecorderOptions {
+    /// File to record to. With rotation or a ring buffer, each file is named after it
+    /// with the time it was started, e.g. `capture-1700000000.jsonl`.
+    pub path: PathBuf,
+
+    /// Start a new file once the current one reaches this size (in ring buffer mode too).
+    pub max_bytes: Option<u64>,
+
+    /// Start a new file once the current one covers this long (in ring buffer mode too).
+    pub max_duration: Option<Duration>,
+
+    /// Only keep this much in memory, and write it out when a trigger frame is received.
+    pub ring: Option<Duration>,
+
+    /// How long to keep recording after a trigger (ring buffer mode).
+    pub post_trigger: Duration,
+
+    pub triggers: Vec<Trigger>
+}
+
+struct RecordingFile {
+    output: BufWriter<File>,
+    bytes: u64,
+    started_us: u64
+}
+
+/// Records frames to JSONL files, one object per frame, e.g.
+///
+/// `{"elapsed_us":1234567,"timestamp_us":1700000001234567,"bytes":"3284ebf9009634"}`
+///
+/// `bytes` is the frame as received on the wire (including corrupted frames). `timestamp_us` is
+/// the wall clock time at the start of the recording plus `elapsed_us`, so it doesn't jump with NTP.
+pub struct Recorder {
+    options: RecorderOptions,
+
+    start: Instant,
+    start_time: SystemTime,
+
+    file: Option<RecordingFile>,
+
+    /// Frames kept in ring buffer mode.
+    ring: VecDeque<RecordedFrame>,
+
+    /// Keep writing to the current file until then (ring buffer mode).
+    recording_until_us: Option<u64>
+}
+
+impl Recorder {
+    pub fn new(options: RecorderOptions) -> Self {
+        Self {
+            options,
+            start: Instant::now(),
+            start_time: SystemTime::now(),
+            file: None,
+            ring: VecDeque::new(),
+            recording_until_us: None
+        }
+    }
+
+    /// Record a frame received now.
+    pub fn record(&mut self, frame: &RxFrame) -> Result<()> {
+        let elapsed_us = self.start.elapsed().as_micros() as u64;
+        self.record_at(RecordedFrame { elapsed_us, frame: frame.clone() })
+    }
+
+    pub fn record_at(&mut self, recorded: RecordedFrame) -> Result<()> {
+        let Some(ring) = self.options.ring else {
+            return self.write(&recorded)
+        };
+
+        if self.recording_until_us.is_some_and(|until_us| recorded.elapsed_us > until_us) {
+            self.recording_until_us = None;
+            self.file = None;
+        }
+
+        let triggered = self.options.triggers.iter().any(|trigger| trigger.matches(&recorded.frame));
+
+        if triggered {
+            self.recording_until_us = Some(recorded.elapsed_us + self.options.post_trigger.as_micros() as u64);
+        }
+
+        if self.recording_until_us.is_some() {
+            // write out the frames leading up to the trigger first
+            while let Some(buffered) = self.ring.pop_front() {
+                self.write(&buffered)?;
+            }
+            return self.write(&recorded)
+        }
+
+        let window_us = ring.as_micros() as u64;
+        while self.ring.front().is_some_and(|oldest| recorded.elapsed_us.saturating_sub(oldest.elapsed_us) > window_us) {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(recorded);
+
+        Ok(())
+    }
+
+    fn should_rotate(&self, elapsed_us: u64) -> bool {
+        let Some(file) = &self.file else { return false };
+
+        self.options.max_bytes.is_some_and(|max_bytes| file.bytes >= max_bytes)
+            || self.options.max_duration.is_some_and(|max_duration| elapsed_us - file.started_us >= max_duration.as_micros() as u64)
+    }
+
+    fn timestamp_us(&self, elapsed_us: u64) -> u64 {
+        let start_us = self.start_time.duration_since(UNIX_EPOCH).map(|t| t.as_micros() as u64).unwrap_or_default();
+        start_us + elapsed_us
+    }
+
+    /// The path of a new file starting at `elapsed_us`.
+    fn file_path(&self, elapsed_us: u64) -> PathBuf {
+        let options = &self.options;
+        if options.max_bytes.is_none() && options.max_duration.is_none() && options.ring.is_none() {
+            return options.path.clone()
+        }
+
+        let stem = options.path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = options.path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+        let seconds = self.timestamp_us(elapsed_us) / 1_000_000;
+
+        (0..)
+            .map(|n| match n {
+                0 => options.path.with_file_name(format!("{stem}-{seconds}{extension}")),
+                n => options.path.with_file_name(format!("{stem}-{seconds}-{n}{extension}")),
+            })
+            .find(|path| !path.exists())
+            .unwrap()
+    }
+
+    fn write(&mut self, recorded: &RecordedFrame) -> Result<()> {
+        if self.should_rotate(recorded.elapsed_us) {
+            self.file = None;
+        }
+
+        if self.file.is_none() {
+            let path = self.file_path(recorded.elapsed_us);
+            let file = File::create(&path)
+                .with_context(|| format!("failed to create recording {}", path.display()))?;
+
+            // stdout may be a capture
+            eprintln!("recording to {}", path.display());
+
+            self.file = Some(RecordingFile {
+                output: BufWriter::new(file),
+                bytes: 0,
+                started_us: recorded.elapsed_us
+            });
+        }
+
+        let record = json!({
+            "elapsed_us": recorded.elapsed_us,
+            "timestamp_us": self.timestamp_us(recorded.elapsed_us),
+            "bytes": recorded.frame.to_bytes().iter().map(|b| format!("{b:02x}")).collect::<String>(),
+        });
+        let line = format!("{record}\n");
+
+        let file = self.file.as_mut().unwrap();
+        file.output.write_all(line.as_bytes())?;
+        file.output.flush()?;
+        file.bytes += line.len() as u64;
+
+        Ok(())
+    }
+}
+
+/// Read the frames of a recording.
+pub fn read_recording(path: &Path) -> Result<Vec<RecordedFrame>> {
+    let recording = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read recording {}", path.display()))?;
+
+    parse_recording(&recording)
+        .with_context(|| format!("failed to parse recording {}", path.display()))
+}
+
+/// Parse all frames from a recording.
+pub fn parse_recording(recording: &str) -> Result<Vec<RecordedFrame>> {
+    recording.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let record: Value = serde_json::from_str(line)
+                .with_context(|| format!("line {}: invalid json", i + 1))?;
+
+            let elapsed_us = record["elapsed_us"].as_u64()
+                .with_context(|| format!("line {}: missing elapsed_us", i + 1))?;
+            let hex = record["bytes"].as_str()
+                .with_context(|| format!("line {}: missing bytes", i + 1))?;
+
+            let bytes = (0..hex.len()).step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .with_context(|| format!("line {}: invalid bytes", i + 1))?;
+
+            Ok(RecordedFrame { elapsed_us, frame: RxFrame::from_bytes(&bytes) })
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::codec::LongFrame;
+
+    use super::*;
+
+    #[test]
+    fn test_recorder_ring() {
+        let dir = std::env::temp_dir().join(format!("samsunghvac2mqtt-test-recorder-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut recorder = Recorder::new(RecorderOptions {
+            path: dir.join("capture.jsonl"),
+            max_bytes: None,
+            max_duration: None,
+            ring: Some(Duration::from_secs(10)),
+            post_trigger: Duration::from_secs(2),
+            triggers: vec![Trigger::ChecksumError, Trigger::Reset]
+        });
+
+        let frame = |elapsed_ms: u64, src, dst, cmd| RecordedFrame {
+            elapsed_us: elapsed_ms * 1000,
+            frame: RxFrame::Long(LongFrame::new(src, dst, cmd, [0; 8]))
+        };
+
+        recorder.record_at(frame(0, MAIN_WRC, 0x20, 0x52)).unwrap();
+        recorder.record_at(frame(5_000, MAIN_WRC, BROADCAST, CommandD1::ID)).unwrap();
+        recorder.record_at(frame(12_000, MAIN_WRC, 0x20, 0x53)).unwrap();
+
+        // nothing is written until the trigger
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        // sub WRC bus reset
+        recorder.record_at(frame(13_000, SUB_WRC, MAIN_WRC, CommandD1::ID)).unwrap();
+        recorder.record_at(frame(14_000, MAIN_WRC, 0x20, 0x54)).unwrap();
+        recorder.record_at(frame(16_000, MAIN_WRC, 0x20, 0x55)).unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].file_name().unwrap().to_string_lossy().starts_with("capture-"));
+
+        // the frame at 0 s dropped out of the ring buffer, the frame at 16 s is after the post trigger window
+        let recorded = read_recording(&files[0]).unwrap();
+        let elapsed_ms: Vec<_> = recorded.iter().map(|recorded| recorded.elapsed_us / 1000).collect();
+        assert_eq!(elapsed_ms, [5_000, 12_000, 13_000, 14_000]);
+        assert_eq!(recorded[2].frame.id().map(|id| (id.src, id.cmd)), Some((SUB_WRC, CommandD1::ID)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recorder_ring_rotation() {
+        let dir = std::env::temp_dir().join(format!("samsunghvac2mqtt-test-recorder-rotation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut recorder = Recorder::new(RecorderOptions {
+            path: dir.join("capture.jsonl"),
+            max_bytes: None,
+            max_duration: Some(Duration::from_secs(5)),
+            ring: Some(Duration::from_secs(10)),
+            post_trigger: Duration::from_secs(20),
+            triggers: vec![Trigger::Reset]
+        });
+
+        // only the sub WRC's bus reset at 1 s triggers, the rest are the main WRC's end of phase broadcasts
+        for elapsed_ms in [0, 1_000, 3_000, 6_000, 12_000, 25_000] {
+            let (src, dst) = if elapsed_ms == 1_000 { (SUB_WRC, MAIN_WRC) } else { (MAIN_WRC, BROADCAST) };
+            let frame = RxFrame::Long(LongFrame::new(src, dst, CommandD1::ID, [0; 8]));
+            recorder.record_at(RecordedFrame { elapsed_us: elapsed_ms * 1000, frame }).unwrap();
+        }
+
+        // the post trigger window (up to 21 s) is split into files of at most 5 s
+        let mut files: Vec<Vec<u64>> = std::fs::read_dir(&dir).unwrap()
+            .map(|entry| read_recording(&entry.unwrap().path()).unwrap().iter().map(|recorded| recorded.elapsed_us / 1000).collect())
+            .collect();
+        files.sort();
+        assert_eq!(files, [vec![0, 1_000, 3_000], vec![6_000], vec![12_000]]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
\ No newline at end of file
@@ -0,0 +1,172 @@
+use std::{path::PathBuf, pin::Pin, task::{Context, Poll}, time::Duration};
+
+use futures::{Stream, Sink, StreamExt, stream};
+use tokio::time::{Instant, sleep_until};
+use percent_encoding::percent_decode_str;
+use url::Url;
+use anyhow::{Result, Context as _, anyhow};
+
+use crate::{capture::parse_frames, recorder::parse_recording, protocol::codec::{RxFrame, TxFrame}};
+
+
+/// A port that plays back frames from a capture in the `dump` tool's text output format,
+/// or a recording from `dump --record` (`.jsonl`).
+///
+/// Frames written to the port are logged and discarded.
+pub struct ReplayPort {
+    frames: Pin<Box<dyn Stream<Item = std::io::Result<RxFrame>> + Send>>
+}
+
+/// The file path of a `replay://` URL.
+fn replay_path(url: &Url) -> Result<PathBuf> {
+    match url.host_str() {
+        None => url.to_file_path()
+            .map_err(|_| anyhow!("invalid replay path in url: {url}")),
+
+        // the first component of a relative path is parsed as the host
+        Some(host) => {
+            let path = format!("{host}{}", url.path());
+            let path = percent_decode_str(&path).decode_utf8()
+                .with_context(|| format!("invalid replay path in url: {url}"))?;
+
+            Ok(PathBuf::from(path.into_owned()))
+        }
+    }
+}
+
+impl ReplayPort {
+    /// Open a `replay://` URL.
+    ///
+    /// Relative paths are given as `replay://dumps/capture.txt`, absolute paths as `replay:///path/to/capture.txt`.
+    /// Paths are percent-decoded (e.g. `replay://dumps/My%20Capture.txt`). The first component of a relative path
+    /// is the URL's host, which URL parsers may case-fold, so use an absolute path if its case matters.
+    ///
+    /// The `speed` query parameter scales the recorded timing (e.g. `speed=2` plays back twice as fast).
+    /// `speed=0` disables timing and plays back the frames as fast as possible.
+    pub async fn open(url: &Url) -> Result<Self> {
+        let path = replay_path(url)?;
+
+        let mut speed = 1.0;
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "speed" => {
+                    speed = value.parse().ok()
+                        .filter(|&speed: &f64| speed >= 0.0 && speed.is_finite())
+                        .ok_or_else(|| anyhow!("invalid value for replay option speed: \"{value}\""))?;
+                },
+                other => anyhow::bail!("unknown replay option \"{other}\" in url: {url}")
+            }
+        }
+
+        // (time since the start of the capture, frame)
+        let frames: Vec<(Duration, RxFrame)> = if path.extension().is_some_and(|extension| extension == "jsonl") {
+            let recording = tokio::fs::read_to_string(&path).await
+                .with_context(|| format!("failed to read recording {}", path.display()))?;
+
+            parse_recording(&recording)
+                .with_context(|| format!("failed to parse recording {}", path.display()))?
+                .into_iter()
+                .map(|recorded| (Duration::from_micros(recorded.elapsed_us), recorded.frame))
+                .collect()
+        } else {
+            let capture = tokio::fs::read_to_string(&path).await
+                .with_context(|| format!("failed to read capture {}", path.display()))?;
+
+            parse_frames(&capture)
+                .with_context(|| format!("failed to parse capture {}", path.display()))?
+                .into_iter()
+                .map(|captured| (Duration::from_millis(captured.elapsed_ms), captured.frame))
+                .collect()
+        };
+
+        let start = Instant::now();
+        let first_elapsed = frames.first().map(|(elapsed, _)| *elapsed).unwrap_or_default();
+
+        let frames = stream::iter(frames)
+            .then(move |(elapsed, frame)| async move {
+                if speed > 0.0 {
+                    let offset = elapsed.saturating_sub(first_elapsed);
+
+                    sleep_until(start + offset.div_f64(speed)).await;
+                }
+
+                Ok(frame)
+            });
+
+        Ok(Self {
+            frames: Box::pin(frames)
+        })
+    }
+}
+
+impl Stream for ReplayPort {
+    type Item = std::io::Result<RxFrame>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.frames.poll_next_unpin(cx)
+    }
+}
+
+impl Sink<TxFrame> for ReplayPort {
+    type Error = std::io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, frame: TxFrame) -> Result<(), Self::Error> {
+        println!("replay: discarding frame: {frame:x?}");
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use crate::config::Port;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replay_capture() {
+        let url = Url::parse("replay://dumps/wrc-main+sub-boot-1IU.txt?speed=0").unwrap();
+
+        let frames: Vec<RxFrame> = Port::open(&url).await.unwrap()
+            .framed()
+            .try_collect().await.unwrap();
+
+        assert!(frames.len() > 1000);
+
+        let id = frames[0].id().unwrap();
+        assert_eq!((id.src, id.dst, id.cmd), (0x84, 0xeb, 0xfb));
+    }
+
+    #[tokio::test]
+    async fn test_replay_invalid_options() {
+        let url = Url::parse("replay://dumps/wrc-main+sub-boot-1IU.txt?speed=fast").unwrap();
+        assert!(ReplayPort::open(&url).await.is_err());
+
+        let url = Url::parse("replay://dumps/does-not-exist.txt").unwrap();
+        assert!(ReplayPort::open(&url).await.is_err());
+    }
+
+    #[test]
+    fn test_replay_path() {
+        let path = |url: &str| replay_path(&Url::parse(url).unwrap()).unwrap();
+
+        assert_eq!(path("replay://dumps/My%20Capture.txt"), PathBuf::from("dumps/My Capture.txt"));
+        assert_eq!(path("replay:///var/lib/My%20Capture.jsonl"), PathBuf::from("/var/lib/My Capture.jsonl"));
+    }
+}
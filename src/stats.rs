@@ -0,0 +1,232 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{bitdiff::GroupKey, export::Direction, protocol::{codec::{RxFrame, FrameId}, addresses::*, commands::{Command, CommandD1}}};
+
+
+/// Bits per byte on the wire (start bit, 8 data bits, even parity, stop bit).
+const BITS_PER_BYTE: u64 = 11;
+
+/// Baud rate of the COM2/WRC bus.
+const BAUD_RATE: u64 = 2400;
+
+/// Minimum, average and maximum of a series of intervals.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timing {
+    pub count: u64,
+    pub total_ms: u64,
+    pub min_ms: u64,
+    pub max_ms: u64
+}
+
+impl Timing {
+    pub fn add(&mut self, ms: u64) {
+        self.min_ms = if self.count == 0 { ms } else { self.min_ms.min(ms) };
+        self.max_ms = self.max_ms.max(ms);
+        self.total_ms += ms;
+        self.count += 1;
+    }
+
+    pub fn avg_ms(&self) -> Option<u64> {
+        self.total_ms.checked_div(self.count)
+    }
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.avg_ms() {
+            Some(avg_ms) => write!(f, "min {} ms, avg {avg_ms} ms, max {} ms ({} samples)", self.min_ms, self.max_ms, self.count),
+            None => write!(f, "no samples"),
+        }
+    }
+}
+
+/// Sorted addresses as ranges, e.g. `22-33 35 50-6f`.
+fn address_ranges(addresses: &[u8]) -> String {
+    let mut ranges: Vec<(u8, u8)> = Vec::new();
+
+    for &address in addresses {
+        match ranges.last_mut() {
+            Some((_, last)) if last.checked_add(1) == Some(address) => *last = address,
+            _ => ranges.push((address, address)),
+        }
+    }
+
+    ranges.iter()
+        .map(|(first, last)| if first == last { format!("{first:02x}") } else { format!("{first:02x}-{last:02x}") })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Health statistics of the bus, from the frames received on a port.
+///
+/// Every request from a WRC to a unit (or the other WRC) is expected to be answered by the
+/// next frame on the bus. The reply latency is measured between the end of the request and
+/// the end of the reply, so includes the transmission time of the reply (~60 ms for a long frame).
+#[derive(Debug, Default)]
+pub struct BusStats {
+    first_ms: Option<u64>,
+    last_ms: u64,
+
+    /// Bytes received, including corrupted frames.
+    bytes: u64,
+
+    pub frames: BTreeMap<GroupKey, u64>,
+
+    /// Reply latency by unit address.
+    pub latency: BTreeMap<u8, Timing>,
+
+    /// Requests not answered by the next frame, by unit address.
+    pub missing_replies: BTreeMap<u8, u64>,
+
+    pub checksum_errors: u64,
+
+    /// Number of times bytes were skipped to find the start of a frame.
+    pub resyncs: u64,
+    pub skipped_bytes: u64,
+
+    /// Time between `0xd1` end of phase broadcasts.
+    pub cycle: Timing,
+
+    last_end_of_phase_ms: Option<u64>,
+
+    /// Request awaiting a reply, and when it was received.
+    pending: Option<(u64, FrameId)>
+}
+
+impl BusStats {
+    /// Add a frame received `elapsed_ms` after the start of the capture.
+    pub fn add(&mut self, elapsed_ms: u64, frame: &RxFrame) {
+        self.first_ms.get_or_insert(elapsed_ms);
+        self.last_ms = elapsed_ms;
+        self.bytes += frame.to_bytes().len() as u64;
+
+        let Some(id) = frame.id() else {
+            if frame.is_invalid_checksum() {
+                self.checksum_errors += 1;
+            } else {
+                self.resyncs += 1;
+                self.skipped_bytes += frame.to_bytes().len() as u64;
+            }
+
+            // the reply may have been corrupted
+            self.pending = None;
+            return
+        };
+
+        *self.frames.entry(GroupKey { src: AddressClass::of(id.src), dst: AddressClass::of(id.dst), cmd: id.cmd }).or_default() += 1;
+
+        if let Some((request_ms, request)) = self.pending.take() {
+            if id.src == request.dst && id.dst == request.src {
+                self.latency.entry(request.dst).or_default().add(elapsed_ms.saturating_sub(request_ms));
+            } else {
+                *self.missing_replies.entry(request.dst).or_default() += 1;
+            }
+        }
+
+        if Direction::of(id) == Some(Direction::Request) && AddressClass::of(id.dst) != AddressClass::Broadcast {
+            self.pending = Some((elapsed_ms, id));
+        }
+
+        if (id.src, id.dst, id.cmd) == (MAIN_WRC, BROADCAST, CommandD1::ID) {
+            if let Some(last_ms) = self.last_end_of_phase_ms.replace(elapsed_ms) {
+                self.cycle.add(elapsed_ms.saturating_sub(last_ms));
+            }
+        }
+    }
+
+    /// Milliseconds between the first and last frame.
+    pub fn duration_ms(&self) -> u64 {
+        self.first_ms.map(|first_ms| self.last_ms - first_ms).unwrap_or_default()
+    }
+
+    /// Fraction of the time the bus was transmitting.
+    pub fn utilisation(&self) -> Option<f64> {
+        let duration_ms = self.duration_ms();
+        if duration_ms == 0 {
+            return None
+        }
+
+        let busy_ms = (self.bytes * BITS_PER_BYTE * 1000) as f64 / BAUD_RATE as f64;
+        Some(busy_ms / duration_ms as f64)
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let seconds = self.duration_ms() as f64 / 1000.0;
+        let per_second = |count: u64| if seconds > 0.0 { count as f64 / seconds } else { 0.0 };
+
+        let frames: u64 = self.frames.values().sum();
+        writeln!(report, "{frames} frames in {seconds:.1} s ({:.2}/s)", per_second(frames)).unwrap();
+
+        if let Some(utilisation) = self.utilisation() {
+            writeln!(report, "bus busy {:.1}% of the time ({:.1}% spare)", utilisation * 100.0, (1.0 - utilisation).max(0.0) * 100.0).unwrap();
+        }
+
+        writeln!(report, "checksum errors: {}, resyncs: {} ({} bytes skipped)", self.checksum_errors, self.resyncs, self.skipped_bytes).unwrap();
+        writeln!(report, "end of phase cycle: {}", self.cycle).unwrap();
+
+        writeln!(report, "frames:").unwrap();
+        for (key, count) in &self.frames {
+            writeln!(report, "  {} -> {} {:02x}: {count} ({:.2}/s)", key.src.name(), key.dst.name(), key.cmd, per_second(*count)).unwrap();
+        }
+
+        writeln!(report, "replies:").unwrap();
+        for (address, latency) in &self.latency {
+            let missing = self.missing_replies.get(address).copied().unwrap_or_default();
+            writeln!(report, "  {address:02x}: {latency}, {missing} missing").unwrap();
+        }
+
+        // e.g. addresses polled during enumeration
+        let silent: Vec<u8> = self.missing_replies.keys().copied()
+            .filter(|address| !self.latency.contains_key(address))
+            .collect();
+        if !silent.is_empty() {
+            let requests: u64 = silent.iter().map(|address| self.missing_replies[address]).sum();
+            writeln!(report, "  never replied ({requests} requests): {}", address_ranges(&silent)).unwrap();
+        }
+
+        report
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::codec::{LongFrame, ShortFrame};
+
+    use super::*;
+
+    #[test]
+    fn test_bus_stats() {
+        let mut stats = BusStats::default();
+
+        let long = |src, dst, cmd| RxFrame::Long(LongFrame::new(src, dst, cmd, [0; 8]));
+
+        stats.add(0, &long(MAIN_WRC, BROADCAST, CommandD1::ID));
+        stats.add(300, &long(MAIN_WRC, 0x20, 0x52));
+        stats.add(370, &long(0x20, MAIN_WRC, 0x52));
+        stats.add(500, &long(MAIN_WRC, 0x21, 0x52));
+        // no reply from 0x21
+        stats.add(800, &long(MAIN_WRC, 0x20, 0x53));
+        stats.add(880, &long(0x20, MAIN_WRC, 0x53));
+        stats.add(900, &RxFrame::Corrupted(vec![0x32, 0x84, 0xeb, 0xf9, 0x00, 0x97, 0x34]));
+        stats.add(950, &RxFrame::Corrupted(vec![0x01, 0x02]));
+        stats.add(1000, &RxFrame::Short(ShortFrame::new(MAIN_WRC, TRACKING_BROADCAST, 0xf9, [0])));
+        stats.add(2000, &long(MAIN_WRC, BROADCAST, CommandD1::ID));
+
+        assert_eq!(stats.latency[&0x20], Timing { count: 2, total_ms: 150, min_ms: 70, max_ms: 80 });
+        assert!(!stats.latency.contains_key(&0x21));
+        assert_eq!(stats.missing_replies.get(&0x21), Some(&1));
+        assert_eq!(stats.missing_replies.get(&0x20), None);
+
+        assert_eq!((stats.checksum_errors, stats.resyncs, stats.skipped_bytes), (1, 1, 2));
+        assert_eq!(stats.cycle, Timing { count: 1, total_ms: 2000, min_ms: 2000, max_ms: 2000 });
+
+        let report = stats.report();
+        assert!(report.starts_with("8 frames in 2.0 s (4.00/s)\n"));
+        assert!(report.contains("  main -> iu 52: 2 (1.00/s)\n"));
+        assert!(report.contains("  20: min 70 ms, avg 75 ms, max 80 ms (2 samples), 0 missing\n"));
+        assert!(report.contains("  never replied (1 requests): 21\n"));
+        assert_eq!(address_ranges(&[0x22, 0x23, 0x24, 0x26, 0x50, 0x51]), "22-24 26 50-51");
+    }
+}
@@ -1,61 +1,1065 @@
-use std::time::Duration;
+use std::{time::Duration, collections::{BTreeMap, BTreeSet, VecDeque}, future::Future, path::PathBuf, sync::Arc};
 
-use futures::{StreamExt, TryStreamExt, SinkExt};
-use samsunghvac2mqtt::{protocol::{commands::*, codec::*, addresses::*}, config::PortStream};
-use tokio::{sync::{mpsc, broadcast}, time::sleep};
+use futures::{TryStreamExt, SinkExt, future::join_all};
+use tokio::{sync::{mpsc, broadcast, oneshot}, time::{sleep, Instant}};
 
+use anyhow::{Result, anyhow, bail};
 
-use anyhow::Result;
+use crate::{config::PortStream, cache::{BusCache, reported_indoor_units}, inventory::{EnumerationObserver, BusInventory}, tracker::{IndoorUnitTracker, StateChange}, capabilities::CapabilityDetector, controller::{HvacController, ControlRequest, Setting, Target}, protocol::{commands::*, codec::*, addresses::*, iu::IndoorUnitCapabilities}};
 
 
-enum NextCommand {
-    Info1,
-    Info2,
-    Info3,
-    Info4,
+/// Delay between acknowledging a handover and sending a request to an IU.
+/// (as observed from a physical sub WRC)
+const REQUEST_DELAY: Duration = Duration::from_millis(180);
 
+/// How long to wait for an IU to reply to a request.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long to wait for an IU to ack and confirm a settings change.
+const CHANGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Minimum time between saves of the state file for IU state changes.
+/// (topology and capability changes are saved immediately)
+const CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The requests the sub WRC sends to each IU, in order, as (command, EEPROM address).
+///
+/// The first 36 are the rotation of the physical sub WRC in `dumps/wrc-main+sub-boot-1IU.txt`:
+/// `0x52`, `0x53`, `0x54` followed by one "other" request, cycling through `0x63` and `0x70`/`0x71`
+/// EEPROM reads of addresses `0x02`, `0x0a` and `0x01`.
+///
+/// The physical sub WRC never sends `0x55`. It's added at the end, as the replies to it are how
+/// IUs with humidity/CO2 sensors are detected.
+const POLL_ROTATION: [(u8, u8); 40] = [
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (Command63Request::ID, 0),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (EepromLowReadRequest::ID, 0x02),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (EepromHighReadRequest::ID, 0x02),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (Command63Request::ID, 0),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (EepromLowReadRequest::ID, 0x0a),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (EepromHighReadRequest::ID, 0x0a),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (Command63Request::ID, 0),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (EepromLowReadRequest::ID, 0x01),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (EepromHighReadRequest::ID, 0x01),
+    (Command52Request::ID, 0), (Command53Request::ID, 0), (Command54Request::ID, 0), (Command55Request::ID, 0),
+];
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChangePhase {
+    /// Waiting for the next handover
+    Queued,
+
+    /// `0xa0` sent, waiting for the IU's `0x50` ack
+    Sent(Instant),
+
+    /// Acked, waiting for the IU's next `0x52` reply to confirm
+    Acked(Instant)
+}
+
+/// A settings change for an IU, merged from one or more control requests.
+struct PendingChange {
+    address: u8,
+    settings: Vec<Setting>,
+    waiters: Vec<oneshot::Sender<Result<()>>>,
+    phase: ChangePhase
 }
 
-struct IndoorUnitState {
-    power: bool,
+impl PendingChange {
+    fn complete(self, result: Result<()>) {
+        let message = result.as_ref().err().map(|e| e.to_string());
+
+        for waiter in self.waiters {
+            let _ = waiter.send(match &message {
+                Some(message) => Err(anyhow!("{message}")),
+                None => Ok(())
+            });
+        }
+    }
 }
 
+/// Something the sub WRC learned about the bus.
+#[derive(Clone, Debug)]
+pub enum SubWrcEvent {
+    /// Bus enumeration completed.
+    Joined(BusInventory),
 
-pub async fn sub_wrc_task(mut port: Box<dyn PortStream>) -> Result<()> {
+    /// A field of an IU's state changed.
+    StateChanged(StateChange),
 
-    /// Force a bus reset
-    /// Wait for the main WRC to enumerate the bus
-    /// Observe the enumeration and make note of the 
+    /// An IU's detected capabilities changed.
+    Capabilities { address: u8, capabilities: IndoorUnitCapabilities },
 
-    // port.try_filter_map(|frame| )
+    /// An ERV's power state changed (or was first reported).
+    ErvPower { address: u8, power: bool },
 
-    while let Some(frame) = port.try_next().await? {
-        let frame_id = if let Some(id) = frame.id() { id } else { continue };
+    /// An IU whose state was restored from the state file replied to a poll, so its state is current.
+    Refreshed { address: u8 },
+
+    /// The unit count reported by the main WRC disagreed with the state file.
+    /// The restored units that hadn't been refreshed (listed) are dropped.
+    CacheInvalidated(BusInventory),
+}
+
+pub struct SubWrcOptions {
+    /// Addresses of IUs to poll.
+    ///
+    /// IUs seen replying to the main WRC are polled too.
+    /// Once bus enumeration has been observed, only the IUs it found are polled.
+    pub indoor_units: Vec<u8>,
+
+    /// Force the main WRC to reset on start, so that the sub WRC can observe
+    /// bus tracking and enumeration to discover the IUs/ERVs on the bus.
+    pub force_reset: bool,
+
+    /// Passively monitor the bus without ever transmitting.
+    ///
+    /// IU state is learned from the replies to the main WRC's (and any physical sub WRC's) polls,
+    /// and settings changes made from a physical remote are observed via their `0x50` acks.
+    pub listen_only: bool,
+
+    /// Poll the IUs, but reject settings changes.
+    pub read_only: bool,
+
+    /// Minimum time between polls of the IUs.
+    ///
+    /// Settings changes (and the polls confirming them) are sent on the next handover regardless.
+    pub poll_interval: Duration,
+
+    /// File to save the bus topology and last known IU states to.
+    ///
+    /// They're restored from it on start (see [HvacController::restored]), and the restored IUs are polled.
+    pub state_file: Option<PathBuf>,
+}
+
+
+struct SubWrc {
+    port: Box<dyn PortStream>,
+
+    indoor_units: BTreeSet<u8>,
+    tracker: IndoorUnitTracker,
+    capabilities: CapabilityDetector,
+    erv_power: BTreeMap<u8, bool>,
+
+    /// Index into the (IU, `POLL_ROTATION`) schedule of the next request to send.
+    next_poll: usize,
+
+    /// Request awaiting a reply from an IU.
+    pending_request: Option<(FrameId, Instant)>,
+
+    requests: mpsc::Receiver<ControlRequest>,
+    changes: Vec<PendingChange>,
+
+    /// IUs to poll with `0x52` next (to confirm an acked change).
+    confirm_polls: VecDeque<u8>,
+
+    poll_interval: Duration,
+    last_poll: Option<Instant>,
+
+    /// Topology and IU states to save to the state file.
+    cache: BusCache,
+    state_file: Option<PathBuf>,
+    cache_dirty: bool,
+    cache_saved: Instant,
+
+    /// The restored topology has been confirmed (or replaced) by the main WRC.
+    cache_confirmed: bool,
+
+    /// IUs polled only because they were restored.
+    restored_indoor_units: BTreeSet<u8>,
+
+    events: broadcast::Sender<SubWrcEvent>,
+
+    listen_only: bool,
+    read_only: bool,
+
+    enumeration: EnumerationObserver
+}
+
+impl SubWrc {
+    async fn send(&mut self, frame: TxFrame) -> Result<()> {
+        if self.listen_only {
+            bail!("sub WRC attempted to transmit while in listen-only mode");
+        }
+
+        Ok(self.port.send(frame).await?)
+    }
+
+    /// Accept requests from the [HvacController], merging them into the queued changes.
+    fn accept_requests(&mut self) {
+        while let Ok(request) = self.requests.try_recv() {
+            if self.listen_only || self.read_only {
+                let mode = if self.listen_only { "listen-only" } else { "read-only" };
+                let _ = request.done.send(Err(anyhow!("can't change settings in {mode} mode")));
+                continue;
+            }
+
+            let addresses: Vec<u8> = match request.target {
+                Target::IndoorUnit(address) => {
+                    if self.tracker.state(address).is_none() {
+                        let _ = request.done.send(Err(anyhow!("state of IU {address:02x} isn't known yet")));
+                        continue;
+                    }
+
+                    if let Some(capabilities) = self.capabilities.capabilities(address) {
+                        if !request.setting.supported_by(capabilities) {
+                            let _ = request.done.send(Err(anyhow!("IU {address:02x} doesn't support {:?}", request.setting)));
+                            continue;
+                        }
+                    }
+
+                    vec![address]
+                },
+
+                Target::All => self.indoor_units.iter().copied()
+                    .filter(|address| self.tracker.state(*address).is_some())
+                    .filter(|address| self.capabilities.capabilities(*address).map_or(true, |c| request.setting.supported_by(c)))
+                    .collect()
+            };
+
+            if addresses.is_empty() {
+                let _ = request.done.send(Err(anyhow!("no IUs to apply {:?} to", request.setting)));
+                continue;
+            }
+
+            let mut results = vec![];
+
+            for address in addresses {
+                let (done, result) = oneshot::channel();
+                results.push(result);
+
+                let change = match self.changes.iter_mut().find(|c| c.address == address && c.phase == ChangePhase::Queued) {
+                    Some(change) => change,
+                    None => {
+                        self.changes.push(PendingChange { address, settings: vec![], waiters: vec![], phase: ChangePhase::Queued });
+                        self.changes.last_mut().unwrap()
+                    }
+                };
+
+                change.settings.retain(|setting| !setting.same_kind(&request.setting));
+                change.settings.push(request.setting);
+                change.waiters.push(done);
+            }
+
+            // complete the request once every IU has confirmed
+            tokio::spawn(async move {
+                let result = join_all(results).await.into_iter()
+                    .map(|result| result.unwrap_or_else(|_| Err(anyhow!("sub WRC task has stopped"))))
+                    .collect::<Result<Vec<_>>>()
+                    .map(|_| ());
+
+                let _ = request.done.send(result);
+            });
+        }
+    }
+
+    /// Fail changes that haven't been acked and confirmed in time.
+    fn expire_changes(&mut self) {
+        let (expired, changes) = std::mem::take(&mut self.changes).into_iter()
+            .partition::<Vec<_>, _>(|change| match change.phase {
+                ChangePhase::Sent(sent) | ChangePhase::Acked(sent) => sent.elapsed() > CHANGE_TIMEOUT,
+                ChangePhase::Queued => false
+            });
+
+        self.changes = changes;
+
+        for change in expired {
+            println!("sub WRC: settings change to IU {:02x} timed out", change.address);
+
+            let address = change.address;
+            change.complete(Err(anyhow!("IU {address:02x} didn't confirm the settings change")));
+        }
+    }
+
+    /// Build the `0xa0` request for the next queued change, if any.
+    fn next_change(&mut self) -> Result<Option<LongFrame>> {
+        let in_flight: BTreeSet<u8> = self.changes.iter()
+            .filter(|change| change.phase != ChangePhase::Queued)
+            .map(|change| change.address)
+            .collect();
+
+        let Some(change) = self.changes.iter_mut()
+            .find(|change| change.phase == ChangePhase::Queued && !in_flight.contains(&change.address)) else { return Ok(None) };
+
+        // merge with the last known state, so only the requested settings change
+        let mut state = self.tracker.state(change.address).cloned().unwrap_or_default();
+        for setting in &change.settings {
+            setting.apply(&mut state);
+        }
+
+        change.phase = ChangePhase::Sent(Instant::now());
+
+        Ok(Some(LongFrame::pack(SUB_WRC, change.address, state.to_a0())?))
+    }
+
+    /// Progress changes awaiting an ack or confirmation from the IU that sent `frame_id`.
+    fn confirm_changes(&mut self, frame_id: FrameId) {
+        let address = frame_id.src;
 
+        let Some(index) = self.changes.iter().position(|change| change.address == address && change.phase != ChangePhase::Queued) else { return };
 
-        let resp: TxFrame = match frame_id.into() {
-            (MAIN_WRC, SUB_WRC, CommandC4Request::ID) => {
-                let ping = frame.unpack_as::<CommandC4Request>()?;
+        match (self.changes[index].phase, frame_id.dst, frame_id.cmd) {
+            (ChangePhase::Sent(sent), SUB_WRC, 0x50) => {
+                self.changes[index].phase = ChangePhase::Acked(sent);
+                self.confirm_polls.push_back(address);
+            },
+
+            (ChangePhase::Acked(_), MAIN_WRC | SUB_WRC, Command52IndoorUnitResponse::ID) => {
+                let change = self.changes.remove(index);
 
-                LongFrame::pack(SUB_WRC, MAIN_WRC, CommandC4Reply::default())?.into()
+                let state = self.tracker.state(address).cloned().unwrap_or_default();
+                let ignored: Vec<_> = change.settings.iter().filter(|setting| !setting.is_applied(&state)).copied().collect();
+
+                if ignored.is_empty() {
+                    change.complete(Ok(()));
+                } else {
+                    println!("sub WRC: IU {address:02x} didn't apply {ignored:?}");
+                    change.complete(Err(anyhow!("IU {address:02x} didn't apply {ignored:?}")));
+                }
             },
 
-            (MAIN_WRC, SUB_WRC, CommandC5Request::ID) => {
-                let ping = frame.unpack_as::<CommandC5Request>()?;
+            _ => ()
+        }
+    }
+
+    /// Send the next request of the schedule (pending settings changes take priority over polls).
+    async fn send_next_request(&mut self) -> Result<()> {
+        if let Some((id, _)) = self.pending_request.take() {
+            println!("sub WRC: no reply from {:02x} to {:02x}", id.dst, id.cmd);
+        }
+
+        self.expire_changes();
+
+        let frame = if let Some(frame) = self.next_change()? {
+            frame
+        } else if let Some(address) = self.confirm_polls.pop_front() {
+            LongFrame::pack(SUB_WRC, address, Command52Request::default())?
+        } else {
+            if self.indoor_units.is_empty() || self.last_poll.is_some_and(|last| last.elapsed() < self.poll_interval) {
+                return Ok(())
+            }
+
+            self.last_poll = Some(Instant::now());
+
+            let n = self.indoor_units.len();
+            let address = *self.indoor_units.iter().nth(self.next_poll % n).unwrap();
+            let (cmd, eeprom_address) = POLL_ROTATION[(self.next_poll / n) % POLL_ROTATION.len()];
+
+            self.next_poll = (self.next_poll + 1) % (n * POLL_ROTATION.len());
+
+            match cmd {
+                Command52Request::ID => LongFrame::pack(SUB_WRC, address, Command52Request::default())?,
+                Command53Request::ID => LongFrame::pack(SUB_WRC, address, Command53Request::default())?,
+                Command54Request::ID => LongFrame::pack(SUB_WRC, address, Command54Request::default())?,
+                Command55Request::ID => LongFrame::pack(SUB_WRC, address, Command55Request::default())?,
+                Command63Request::ID => LongFrame::pack(SUB_WRC, address, Command63Request::default())?,
+                EepromLowReadRequest::ID => LongFrame::pack(SUB_WRC, address, EepromLowReadRequest { address: eeprom_address, ..Default::default() })?,
+                EepromHighReadRequest::ID => LongFrame::pack(SUB_WRC, address, EepromHighReadRequest { address: eeprom_address, ..Default::default() })?,
+                _ => unreachable!()
+            }
+        };
+
+        sleep(REQUEST_DELAY).await;
+
+        self.pending_request = Some((frame.id, Instant::now()));
+        self.send(frame.into()).await?;
+
+        Ok(())
+    }
+
+    /// Handle a reply from an IU to the sub WRC.
+    fn handle_reply(&mut self, address: u8, frame: &RxFrame) {
+        let Some(id) = frame.id() else { return };
+
+        if let Some((pending, sent)) = self.pending_request {
+            if pending.dst == address && (pending.cmd == id.cmd || (pending.cmd == CommandA0::ID && id.cmd == 0x50)) {
+                self.pending_request = None;
+
+                if sent.elapsed() > REPLY_TIMEOUT {
+                    println!("sub WRC: late reply from {address:02x} to {:02x}", id.cmd);
+                }
+            }
+        }
+
+        if id.cmd == Command63Response::ID {
+            match frame.unpack_unchecked::<Command63Response>() {
+                Ok(resp) => println!("IU {address:02x}: {resp:?}"),
+                Err(err) => println!("IU {address:02x}: ignoring frame that failed to unpack ({err}): {frame:?}")
+            }
+        }
+    }
+
+    /// Update the tracked IU state from a frame, and publish any changes.
+    fn track(&mut self, frame: &RxFrame) {
+        let src = frame.id().map(|id| id.src).unwrap_or_default();
+        let stale = self.tracker.is_stale(src);
+
+        let changes = match self.tracker.observe(frame) {
+            Ok(changes) => changes,
+            Err(err) => {
+                // e.g. a mode value that isn't known yet
+                println!("IU {src:02x}: ignoring frame that failed to unpack ({err}): {frame:?}");
+                vec![]
+            }
+        };
+
+        if stale && !self.tracker.is_stale(src) {
+            println!("IU {src:02x}: refreshed");
+            let _ = self.events.send(SubWrcEvent::Refreshed { address: src });
+        }
+
+        if let Some(state) = self.tracker.state(src).filter(|_| !changes.is_empty()) {
+            self.cache.inventory.indoor_units.insert(src);
+            self.cache.states.insert(src, state.clone());
+            self.cache_dirty = true;
+        }
+
+        for change in changes {
+            match change.old {
+                Some(old) => println!("IU {:02x}: {} {old:?} -> {:?}", change.address, change.field.name(), change.new),
+                None => println!("IU {:02x}: {} = {:?}", change.address, change.field.name(), change.new),
+            }
+
+            // no receivers is fine
+            let _ = self.events.send(SubWrcEvent::StateChanged(change));
+        }
 
-                LongFrame::pack(SUB_WRC, MAIN_WRC, CommandC5Response::default())?.into()
+        if let Some(id) = frame.id() {
+            if let (address @ ERV_FIRST..=ERV_LAST, MAIN_WRC | SUB_WRC, Command52ErvResponse::ID) = id.into() {
+                let power = match frame.unpack_unchecked::<Command52ErvResponse>() {
+                    Ok(resp) => resp.power,
+                    Err(err) => {
+                        println!("ERV {address:02x}: ignoring frame that failed to unpack ({err}): {frame:?}");
+                        return
+                    }
+                };
+
+                if self.erv_power.insert(address, power) != Some(power) {
+                    println!("ERV {address:02x}: power = {power}");
+
+                    if self.cache.inventory.ervs.insert(address) {
+                        self.cache_dirty = true;
+                    }
+
+                    let _ = self.events.send(SubWrcEvent::ErvPower { address, power });
+                }
             }
+        }
+    }
+
+    /// Start polling the IUs found by bus enumeration (and stop polling those that weren't).
+    fn joined(&mut self, inventory: BusInventory) {
+        println!("sub WRC: joined bus with IUs {:02x?} and ERVs {:02x?}", inventory.indoor_units, inventory.ervs);
+
+        self.indoor_units = inventory.indoor_units.clone();
+        self.erv_power.retain(|address, _| inventory.ervs.contains(address));
+        self.next_poll = 0;
+
+        self.cache.retain(&inventory);
+        self.cache.inventory = inventory.clone();
+        self.cache_confirmed = true;
+        self.cache_dirty = true;
+        self.save_cache(true);
+
+        let _ = self.events.send(SubWrcEvent::Joined(inventory));
+    }
+
+    /// Check the restored topology against the unit count reported by the main WRC.
+    fn check_unit_count(&mut self, frame: &RxFrame) {
+        if self.cache_confirmed || self.enumeration.in_progress() {
+            return
+        }
 
-            // (MAIN_WRC, BROADCAST, EndOfPhase::ID) => {
-            //     LongFrame::new(SUB_WRC, MAIN_WRC, 0xd1, [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]).into()
-            // }
+        let Some(count) = reported_indoor_units(frame) else { return };
+
+        if count as usize == self.cache.inventory.indoor_units.len() {
+            self.cache_confirmed = true;
+            return
+        }
+
+        println!("sub WRC: main WRC reports {count} IUs, but {} are in the state file, discarding it", self.cache.inventory.indoor_units.len());
+
+        // restored units that haven't been heard from since
+        let dropped = BusInventory {
+            indoor_units: self.cache.inventory.indoor_units.iter().copied()
+                .filter(|address| self.tracker.state(*address).is_none() || self.tracker.is_stale(*address))
+                .collect(),
+            ervs: self.cache.inventory.ervs.iter().copied().filter(|address| !self.erv_power.contains_key(address)).collect()
+        };
+
+        self.tracker.forget_stale();
+
+        for address in &dropped.indoor_units {
+            self.capabilities.forget(*address);
+
+            if self.restored_indoor_units.contains(address) {
+                self.indoor_units.remove(address);
+            }
+        }
 
-            _ => continue
+        let current = BusInventory {
+            indoor_units: self.cache.inventory.indoor_units.difference(&dropped.indoor_units).copied().collect(),
+            ervs: self.cache.inventory.ervs.difference(&dropped.ervs).copied().collect()
         };
 
-        port.send(resp).await?;
+        self.cache.retain(&current);
+        self.cache_confirmed = true;
+        self.cache_dirty = true;
+        self.save_cache(true);
+
+        let _ = self.events.send(SubWrcEvent::CacheInvalidated(dropped));
+    }
+
+    /// Save the cache to the state file if it has changed (at most every `CACHE_SAVE_INTERVAL`, unless forced).
+    fn save_cache(&mut self, force: bool) {
+        let Some(path) = &self.state_file else { return };
+
+        if !self.cache_dirty || (!force && self.cache_saved.elapsed() < CACHE_SAVE_INTERVAL) {
+            return
+        }
+
+        // a failed save shouldn't stop the sub WRC
+        if let Err(e) = self.cache.save(path) {
+            println!("sub WRC: {e:#}");
+        }
+
+        self.cache_dirty = false;
+        self.cache_saved = Instant::now();
+    }
+
+    async fn run(mut self) -> Result<()> {
+        while let Some(frame) = self.port.try_next().await? {
+            let frame_id = if let Some(id) = frame.id() { id } else { continue };
+
+            if let Some(inventory) = self.enumeration.observe(&frame) {
+                self.joined(inventory);
+            }
+
+            self.accept_requests();
+
+            self.check_unit_count(&frame);
+
+            self.track(&frame);
+
+            if let INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST = frame_id.src {
+                self.confirm_changes(frame_id);
+            }
+
+            if let Some((address, capabilities)) = self.capabilities.observe(&frame) {
+                println!("IU {address:02x}: {capabilities:?}");
+
+                self.cache.capabilities.insert(address, capabilities.clone());
+                self.cache_dirty = true;
+                self.save_cache(true);
+
+                let _ = self.events.send(SubWrcEvent::Capabilities { address, capabilities });
+            }
+
+            self.save_cache(false);
+
+            if self.listen_only {
+                self.monitor(frame_id, &frame);
+                continue;
+            }
+
+            match frame_id.into() {
+                // handover from the main WRC -- ack, then take a turn talking to the IUs
+                // (the sub WRC must ack during enumeration too, otherwise the main WRC won't hand over during normal operation)
+                (MAIN_WRC, SUB_WRC, CommandC4Request::ID) => {
+                    if let Err(err) = frame.unpack_unchecked::<CommandC4Request>() {
+                        println!("sub WRC: ignoring handover that failed to unpack ({err}): {frame:?}");
+                        continue;
+                    }
+
+                    self.send(LongFrame::pack(SUB_WRC, MAIN_WRC, CommandC4Reply::default())?.into()).await?;
+
+                    if !self.enumeration.in_progress() {
+                        self.send_next_request().await?;
+                    }
+                },
+
+                (MAIN_WRC, SUB_WRC, CommandC5Request::ID) => {
+                    if let Err(err) = frame.unpack_unchecked::<CommandC5Request>() {
+                        println!("sub WRC: ignoring handover that failed to unpack ({err}): {frame:?}");
+                        continue;
+                    }
+
+                    self.send(LongFrame::pack(SUB_WRC, MAIN_WRC, CommandC5Response::default())?.into()).await?;
+
+                    if !self.enumeration.in_progress() {
+                        self.send_next_request().await?;
+                    }
+                },
+
+                (src @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, SUB_WRC, _) => {
+                    self.handle_reply(src, &frame);
+                },
+
+                // learn about IUs from their replies to the main WRC
+                (src @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, MAIN_WRC, _) => {
+                    if frame_id.cmd == Command52IndoorUnitResponse::ID && self.indoor_units.insert(src) {
+                        println!("sub WRC: found IU {src:02x}");
+                    }
+                },
+
+                _ => continue
+            };
+        };
+
+        self.save_cache(true);
+
+        Ok(())
+    }
+
+    /// Listen-only handling of a frame (IU state is tracked from other WRCs' traffic).
+    fn monitor(&mut self, frame_id: FrameId, frame: &RxFrame) {
+        match frame_id.into() {
+            (src @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, MAIN_WRC | SUB_WRC, _) => {
+                if self.indoor_units.insert(src) {
+                    println!("sub WRC: found IU {src:02x}");
+                }
+            },
+
+            // settings change from a physical remote; the state is updated from the IU's `0x50` ack
+            (src @ (MAIN_WRC | SUB_WRC), dst @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, CommandA0::ID) => {
+                match frame.unpack_unchecked::<CommandA0>() {
+                    Ok(settings) => println!("sub WRC: {src:02x} changing settings of IU {dst:02x}: {settings:?}"),
+                    Err(err) => println!("sub WRC: {src:02x} changing settings of IU {dst:02x}, failed to unpack ({err}): {frame:?}")
+                }
+            },
+
+            _ => ()
+        }
+    }
+}
+
+
+/// Force the main WRC to reset, and hence re-track and re-enumerate the bus.
+///
+/// Waits for the main WRC's end-of-phase broadcast and replies to it.
+pub async fn force_bus_reset(port: &mut Box<dyn PortStream>) -> Result<()> {
+    while let Some(frame) = port.try_next().await? {
+        if let Some(id) = frame.id() {
+            if let (MAIN_WRC, BROADCAST, CommandD1::ID) = id.into() {
+                let frame = LongFrame::pack(SUB_WRC, MAIN_WRC, CommandD1Response::default())?.into();
+
+                port.send(frame).await?;
+
+                return Ok(())
+            }
+        }
+    }
+
+    anyhow::bail!("port closed while waiting for an end-of-phase broadcast")
+}
+
+/// Emulate a sub WRC on the bus attached to `port`.
+///
+/// Polls the IUs for their state when the main WRC hands over the bus,
+/// and sends the settings changes requested via the returned [HvacController].
+/// Everything learned about the bus is published to the controller's subscribers.
+///
+/// The returned task must be polled for the sub WRC to run.
+pub fn sub_wrc_task(port: Box<dyn PortStream>, options: SubWrcOptions) -> (HvacController, impl Future<Output = Result<()>>) {
+    let (requests_send, requests) = mpsc::channel(16);
+    let (events, _) = broadcast::channel(256);
+
+    let restored = match &options.state_file {
+        Some(path) => BusCache::load(path).unwrap_or_else(|e| {
+            // a missing or corrupt state file only means starting from scratch
+            println!("sub WRC: ignoring state file: {e:#}");
+            None
+        }),
+        None => None
     };
 
-    Ok(())
+    let restored = Arc::new(restored.unwrap_or_default());
+
+    let controller = HvacController::new(requests_send, events.clone(), restored.clone());
+
+    (controller, run_sub_wrc(port, options, restored, requests, events))
+}
+
+async fn run_sub_wrc(mut port: Box<dyn PortStream>, options: SubWrcOptions, restored: Arc<BusCache>, requests: mpsc::Receiver<ControlRequest>, events: broadcast::Sender<SubWrcEvent>) -> Result<()> {
+    if options.listen_only {
+        if options.force_reset {
+            bail!("a bus reset can't be forced in listen-only mode");
+        }
+
+        println!("sub WRC: listen-only mode, not transmitting");
+    }
+
+    if options.force_reset {
+        println!("sub WRC: waiting for end-of-phase broadcast to force a bus reset...");
+
+        force_bus_reset(&mut port).await?;
+
+        println!("sub WRC: sent reset, waiting for bus enumeration...");
+    }
+
+    let mut tracker = IndoorUnitTracker::default();
+    let mut capabilities = CapabilityDetector::default();
+
+    if !restored.is_empty() {
+        println!("sub WRC: restored IUs {:02x?} and ERVs {:02x?} from the state file", restored.inventory.indoor_units, restored.inventory.ervs);
+
+        for (address, state) in &restored.states {
+            tracker.restore(*address, state.clone());
+        }
+
+        for (address, unit_capabilities) in &restored.capabilities {
+            capabilities.restore(*address, unit_capabilities.clone());
+        }
+    }
+
+    let indoor_units: BTreeSet<u8> = options.indoor_units.into_iter().collect();
+    let restored_indoor_units = restored.inventory.indoor_units.difference(&indoor_units).copied().collect();
+
+    let sub_wrc = SubWrc {
+        port,
+        indoor_units: indoor_units.union(&restored.inventory.indoor_units).copied().collect(),
+        tracker,
+        capabilities,
+        erv_power: BTreeMap::new(),
+        next_poll: 0,
+        pending_request: None,
+        requests,
+        changes: vec![],
+        confirm_polls: VecDeque::new(),
+        poll_interval: options.poll_interval,
+        last_poll: None,
+        cache: (*restored).clone(),
+        state_file: options.state_file,
+        cache_dirty: false,
+        cache_saved: Instant::now(),
+        cache_confirmed: restored.is_empty(),
+        restored_indoor_units,
+        events,
+        listen_only: options.listen_only,
+        read_only: options.read_only,
+        enumeration: EnumerationObserver::default()
+    };
+
+    sub_wrc.run().await
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::Pin, task::{Context, Poll}, sync::Mutex};
+
+    use futures::{Stream, Sink, StreamExt};
+    use tokio::io::DuplexStream;
+    use tokio_util::codec::Framed;
+    use url::Url;
+
+    use crate::{config::Port, capture::Capture, controller::Target, protocol::iu::{IndoorUnitState, IndoorUnitField, IndoorUnitValue}};
+
+    use super::*;
+
+    /// A port that keeps the frames sent to it.
+    struct RecordingPort {
+        port: Box<dyn PortStream>,
+        sent: Arc<Mutex<Vec<TxFrame>>>
+    }
+
+    impl Stream for RecordingPort {
+        type Item = std::io::Result<RxFrame>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.port.poll_next_unpin(cx)
+        }
+    }
+
+    impl Sink<TxFrame> for RecordingPort {
+        type Error = std::io::Error;
+
+        fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            self.port.poll_ready_unpin(cx)
+        }
+
+        fn start_send(mut self: Pin<&mut Self>, frame: TxFrame) -> std::io::Result<()> {
+            self.sent.lock().unwrap().push(frame.clone());
+            self.port.start_send_unpin(frame)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            self.port.poll_flush_unpin(cx)
+        }
 
-}
\ No newline at end of file
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            self.port.poll_close_unpin(cx)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sub_wrc_replay() {
+        // the capture includes a physical sub WRC polling IU 0x20, exercising reply handling
+        let url = Url::parse("replay://dumps/wrc-main+sub-boot-1IU.txt?speed=0").unwrap();
+        let sent = Arc::new(Mutex::new(vec![]));
+        let port = Box::new(RecordingPort { port: Port::open(&url).await.unwrap().framed(), sent: sent.clone() });
+
+        let options = SubWrcOptions {
+            indoor_units: vec![],
+            force_reset: false,
+            listen_only: false,
+            read_only: false,
+            poll_interval: Duration::ZERO,
+            state_file: None
+        };
+
+        let (_controller, task) = sub_wrc_task(port, options);
+
+        task.await.unwrap();
+
+        let sent_frames = sent.lock().unwrap().clone();
+        let sent: Vec<FrameId> = sent_frames.iter().map(|frame| match frame {
+            TxFrame::Long(frame) => frame.id,
+            TxFrame::Short(frame) => frame.id
+        }).collect();
+
+        assert!(sent.iter().all(|id| id.src == SUB_WRC));
+
+        let capture = Capture::parse(&std::fs::read_to_string("dumps/wrc-main+sub-boot-1IU.txt").unwrap()).unwrap();
+        let handovers = capture.frames.iter()
+            .filter_map(|captured| captured.frame.id())
+            .filter(|id| (id.src, id.dst) == (MAIN_WRC, SUB_WRC) && [CommandC4Request::ID, CommandC5Request::ID].contains(&id.cmd))
+            .count();
+        assert_eq!(sent.iter().filter(|id| id.dst == MAIN_WRC).count(), handovers);
+
+        // the physical sub WRC's rotation in the capture repeats every 36 requests, followed by the added `0x55`
+        let physical: Vec<(u8, u8)> = capture.frames.iter()
+            .filter_map(|captured| match &captured.frame {
+                RxFrame::Long(frame) if (frame.id.src, frame.id.dst) == (SUB_WRC, 0x20) => Some((frame.id.cmd, frame.data[0])),
+                _ => None
+            })
+            .collect();
+        assert!(physical.len() > 36);
+        assert!(physical.iter().eq(POLL_ROTATION[..36].iter().cycle().take(physical.len())));
+        assert_eq!(POLL_ROTATION[36..].iter().map(|(cmd, _)| *cmd).collect::<Vec<_>>(), [0x52, 0x53, 0x54, 0x55]);
+
+        // every handover is acked, and the IU discovered during enumeration is sent one request per handover
+        let requests: Vec<(u8, u8)> = sent_frames.iter()
+            .filter_map(|frame| match frame {
+                TxFrame::Long(frame) if frame.id.dst == 0x20 => Some((frame.id.cmd, frame.data[0])),
+                _ => None
+            })
+            .collect();
+        assert!(requests.len() > POLL_ROTATION.len());
+        assert!(requests.iter().eq(POLL_ROTATION.iter().cycle().take(requests.len())));
+
+        for (i, id) in sent.iter().enumerate() {
+            match id.dst {
+                MAIN_WRC => assert!([CommandC4Reply::ID, CommandC5Response::ID].contains(&id.cmd)),
+                0x20 => assert_eq!(sent[i - 1].dst, MAIN_WRC, "request {i} not preceded by a handover ack"),
+                _ => panic!("unexpected frame {id:?}")
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sub_wrc_listen_only() {
+        let url = Url::parse("replay://dumps/wrc-main+sub-boot-2IU(1emu).txt?speed=0").unwrap();
+        let port = Port::open(&url).await.unwrap().framed();
+
+        let options = SubWrcOptions {
+            indoor_units: vec![],
+            force_reset: false,
+            listen_only: true,
+            read_only: false,
+            poll_interval: Duration::ZERO,
+            state_file: None
+        };
+
+        let (controller, task) = sub_wrc_task(port, options);
+        let mut events = controller.subscribe();
+
+        // any attempt to transmit is an error
+        task.await.unwrap();
+
+        let mut updated = BTreeSet::new();
+        loop {
+            match events.try_recv() {
+                Ok(SubWrcEvent::StateChanged(change)) => { updated.insert(change.address); },
+                Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                Err(_) => break
+            }
+        }
+
+        assert_eq!(updated, BTreeSet::from([0x20, 0x21]));
+    }
+
+    /// Play the main WRC (handing over to the sub WRC) and an IU at `0x20` on an in-memory bus.
+    async fn emulate_main_wrc_and_iu(mut bus: Framed<DuplexStream, WrcBusProtocolCodec>) {
+        let mut state = IndoorUnitState { setpoint_temp: 22, ..Default::default() };
+
+        loop {
+            let handover = CommandC4Request { unknown_byte0: 0x01, number_of_indoor_units: 1, unknown_iu_address: 0x20, unknown_byte7: 0x44 };
+            if bus.send(LongFrame::pack(MAIN_WRC, SUB_WRC, handover).unwrap().into()).await.is_err() {
+                return
+            }
+
+            // the sub WRC acks, then sends its request
+            let request = loop {
+                match tokio::time::timeout(Duration::from_secs(1), bus.try_next()).await {
+                    Ok(Ok(Some(frame))) => match frame.id() {
+                        Some(id) if id.dst == 0x20 => break Some((id, frame)),
+                        _ => continue
+                    },
+                    Ok(_) => return,
+                    Err(_) => break None
+                }
+            };
+
+            let Some((id, frame)) = request else { continue };
+
+            let reply: TxFrame = match id.cmd {
+                CommandA0::ID => {
+                    let cmd = frame.unpack_as::<CommandA0>().unwrap();
+                    state.update_from_a0(&cmd);
+
+                    let mut frame = LongFrame::pack(0x20, SUB_WRC, cmd).unwrap();
+                    frame.id.cmd = 0x50;
+                    frame.into()
+                },
+                Command52Request::ID => {
+                    let resp = Command52IndoorUnitResponse {
+                        setpoint_temp: Temperature::new(state.setpoint_temp, TemperatureUnit::Celsius),
+                        iu_room_temp: Temperature::new(21, TemperatureUnit::Celsius),
+                        iu_eva_inlet_temp: Temperature::new(21, TemperatureUnit::Celsius),
+                        fan_speed: state.fan_speed.raw(),
+                        power: state.power,
+                        unknown_bit33: false,
+                        unknown_bit34: false,
+                        defrost: false,
+                        mode: state.mode.into(),
+                        clean_filter: false,
+                        iu_eva_outlet_temp: Temperature::new(21, TemperatureUnit::Celsius),
+                    };
+
+                    LongFrame::pack(0x20, SUB_WRC, resp).unwrap().into()
+                },
+                _ => continue
+            };
+
+            if bus.send(reply).await.is_err() {
+                return
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hvac_controller() {
+        let (sub_end, bus_end) = tokio::io::duplex(1024);
+
+        tokio::spawn(emulate_main_wrc_and_iu(Framed::new(bus_end, WrcBusProtocolCodec::new())));
+
+        let options = SubWrcOptions {
+            indoor_units: vec![0x20],
+            force_reset: false,
+            listen_only: false,
+            read_only: false,
+            poll_interval: Duration::ZERO,
+            state_file: None
+        };
+
+        let (controller, task) = sub_wrc_task(Box::new(Framed::new(sub_end, WrcBusProtocolCodec::new())), options);
+        let mut events = controller.subscribe();
+
+        tokio::spawn(task);
+
+        // wait until the IU's state is known
+        while !matches!(events.recv().await.unwrap(), SubWrcEvent::StateChanged(_)) {}
+
+        controller.set_power(0x20, true).await.unwrap();
+        controller.set_setpoint(Target::All, 25).await.unwrap();
+
+        let mut power = false;
+        let mut setpoint = 0;
+        while let Ok(event) = events.try_recv() {
+            if let SubWrcEvent::StateChanged(change) = event {
+                match change.new {
+                    IndoorUnitValue::Bool(value) if change.field == IndoorUnitField::Power => power = value,
+                    IndoorUnitValue::Temperature(value) if change.field == IndoorUnitField::SetpointTemp => setpoint = value,
+                    _ => ()
+                }
+            }
+        }
+
+        assert!(power);
+        assert_eq!(setpoint, 25);
+
+        assert!(controller.set_setpoint(0x20, 40).await.is_err());
+        assert!(controller.set_power(0x21, true).await.is_err());
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_state_file() {
+        /// Start the sub WRC (with the emulated main WRC and IU) from a state file.
+        fn start(path: &std::path::Path, cache: &BusCache) -> (HvacController, broadcast::Receiver<SubWrcEvent>) {
+            cache.save(path).unwrap();
+
+            let (sub_end, bus_end) = tokio::io::duplex(1024);
+            tokio::spawn(emulate_main_wrc_and_iu(Framed::new(bus_end, WrcBusProtocolCodec::new())));
+
+            let options = SubWrcOptions {
+                indoor_units: vec![],
+                force_reset: false,
+                listen_only: false,
+                read_only: false,
+                poll_interval: Duration::ZERO,
+                state_file: Some(path.to_path_buf())
+            };
+
+            let (controller, task) = sub_wrc_task(Box::new(Framed::new(sub_end, WrcBusProtocolCodec::new())), options);
+            let events = controller.subscribe();
+
+            tokio::spawn(task);
+
+            (controller, events)
+        }
+
+        let path = std::env::temp_dir().join(format!("samsunghvac2mqtt-test-state-{}.json", std::process::id()));
+
+        // the restored IU is polled, and its stale state refreshed
+        let cache = BusCache {
+            inventory: BusInventory { indoor_units: BTreeSet::from([0x20]), ervs: BTreeSet::new() },
+            states: BTreeMap::from([(0x20, IndoorUnitState { setpoint_temp: 21, ..Default::default() })]),
+            ..Default::default()
+        };
+
+        let (controller, mut events) = start(&path, &cache);
+        assert_eq!(controller.restored(), &cache);
+
+        loop {
+            match events.recv().await.unwrap() {
+                SubWrcEvent::StateChanged(change) if change.field == IndoorUnitField::SetpointTemp => {
+                    assert_eq!((change.old, change.new), (Some(IndoorUnitValue::Temperature(21)), IndoorUnitValue::Temperature(22)));
+                },
+                SubWrcEvent::Refreshed { address } => {
+                    assert_eq!(address, 0x20);
+                    break
+                },
+                SubWrcEvent::CacheInvalidated(_) => panic!("the unit count matches"),
+                _ => ()
+            }
+        }
+
+        // the main WRC reports 1 IU, so a state file with 2 is discarded
+        let cache = BusCache {
+            inventory: BusInventory { indoor_units: BTreeSet::from([0x20, 0x21]), ervs: BTreeSet::new() },
+            ..cache
+        };
+
+        let (_controller, mut events) = start(&path, &cache);
+
+        loop {
+            if let SubWrcEvent::CacheInvalidated(dropped) = events.recv().await.unwrap() {
+                assert_eq!(dropped.indoor_units, BTreeSet::from([0x20, 0x21]));
+                break
+            }
+        }
+
+        assert!(BusCache::load(&path).unwrap().unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
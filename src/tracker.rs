@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+
+use crate::protocol::{codec::{RxFrame, Unpack}, commands::*, addresses::*, iu::{IndoorUnitState, IndoorUnitField, IndoorUnitValue, IndoorUnitFanSpeed}};
+
+
+/// A change to the state of an IU.
+#[derive(Clone, Debug)]
+pub struct StateChange {
+    /// Address of the IU
+    pub address: u8,
+
+    pub field: IndoorUnitField,
+
+    /// Previous value of the field. `None` the first time the field is reported.
+    pub old: Option<IndoorUnitValue>,
+
+    pub new: IndoorUnitValue,
+
+    /// The frame the new value was decoded from.
+    pub source: RxFrame
+}
+
+#[derive(Default)]
+struct TrackedUnit {
+    state: IndoorUnitState,
+
+    /// Fields that have been reported by the IU at least once.
+    known: BTreeSet<IndoorUnitField>,
+
+    /// The state was restored, and the IU hasn't replied to a `0x52` poll since.
+    stale: bool
+}
+
+/// Tracks the live state of every IU on the bus from the frames exchanged with it.
+///
+/// State is decoded from IU replies (to either WRC) to `0x52`, `0x53`, `0x54`, `0x55` and `0x64`,
+/// and from `0x50` settings change acks.
+#[derive(Default)]
+pub struct IndoorUnitTracker {
+    units: BTreeMap<u8, TrackedUnit>
+}
+
+impl IndoorUnitTracker {
+    /// Last known state of an IU.
+    pub fn state(&self, address: u8) -> Option<&IndoorUnitState> {
+        self.units.get(&address).map(|unit| &unit.state)
+    }
+
+    /// Last known states of all IUs seen so far.
+    pub fn states(&self) -> impl Iterator<Item = (u8, &IndoorUnitState)> {
+        self.units.iter().map(|(address, unit)| (*address, &unit.state))
+    }
+
+    /// Restore the last known state of an IU (e.g., from a [crate::cache::BusCache]).
+    ///
+    /// The state is stale until the IU next replies to a `0x52` poll.
+    /// Changes are reported relative to the restored state.
+    pub fn restore(&mut self, address: u8, state: IndoorUnitState) {
+        self.units.insert(address, TrackedUnit { state, known: IndoorUnitField::ALL.into(), stale: true });
+    }
+
+    /// Is the IU's state restored and not yet refreshed?
+    pub fn is_stale(&self, address: u8) -> bool {
+        self.units.get(&address).is_some_and(|unit| unit.stale)
+    }
+
+    /// Forget the IUs whose restored state hasn't been refreshed. Returns their addresses.
+    pub fn forget_stale(&mut self) -> Vec<u8> {
+        let stale: Vec<u8> = self.units.iter().filter(|(_, unit)| unit.stale).map(|(address, _)| *address).collect();
+
+        for address in &stale {
+            self.units.remove(address);
+        }
+
+        stale
+    }
+
+    /// Observe a frame. Returns the changes to IU state it caused, if any.
+    ///
+    /// Frames are unpacked without [crate::protocol::commands::Command::check], as they
+    /// may come from other devices on the bus. Fails if the frame can't be unpacked
+    /// (e.g. an unknown mode), leaving the state unchanged.
+    pub fn observe(&mut self, frame: &RxFrame) -> Result<Vec<StateChange>> {
+        use IndoorUnitField::*;
+
+        let Some(id) = frame.id() else { return Ok(vec![]) };
+
+        let address = match id.into() {
+            (src @ INDOOR_UNIT_FIRST..=INDOOR_UNIT_LAST, MAIN_WRC | SUB_WRC, _) => src,
+            _ => return Ok(vec![])
+        };
+
+        let unit = self.units.entry(address).or_default();
+        let before = unit.state.clone();
+
+        let mut reported = match id.cmd {
+            Command52IndoorUnitResponse::ID => {
+                let resp = frame.unpack_unchecked::<Command52IndoorUnitResponse>()?;
+                unit.state.update_from_52(&resp);
+                unit.stale = false;
+
+                let mut fields = vec![Power, Mode, SetpointTemp, RoomTemp, EvaInletTemp, EvaOutletTemp, Defrost, CleanFilter];
+                if IndoorUnitFanSpeed::from_raw(resp.fan_speed).is_some() {
+                    fields.push(FanSpeed);
+                }
+                fields
+            },
+            Command53IndoorUnitResponse::ID => {
+                let resp = frame.unpack_unchecked::<Command53IndoorUnitResponse>()?;
+                unit.state.update_from_53(&resp);
+                vec![Humidifier]
+            },
+            Command54IndoorUnitResponse::ID => {
+                let resp = frame.unpack_unchecked::<Command54IndoorUnitResponse>()?;
+                unit.state.update_from_54(&resp);
+                vec![OaIntake, AwayMds, SPlasmaIon]
+            },
+            Command55IndoorUnitResponse::ID => {
+                let resp = frame.unpack_unchecked::<Command55IndoorUnitResponse>()?;
+                unit.state.update_from_55(&resp);
+                vec![Humidity, Co2]
+            },
+            Command64Request::ID => {
+                // the IU replies with the request, filling in its own sensor temperature
+                let resp = frame.unpack_unchecked::<Command64Request>()?;
+                unit.state.update_from_64(&resp);
+                vec![IuSensorTemp, WrcSensorTemp]
+            },
+            0x50 => {
+                // settings change ack (echoes the `0xa0` request)
+                let resp = frame.unpack_unchecked::<CommandA0>()?;
+                unit.state.update_from_a0(&resp);
+
+                let mut fields = vec![Power, Mode, SetpointTemp, SPlasmaIon, Humidifier];
+                if IndoorUnitFanSpeed::from_raw(resp.fan_speed).is_some() {
+                    fields.push(FanSpeed);
+                }
+                fields
+            },
+            _ => return Ok(vec![])
+        };
+
+        reported.sort();
+
+        let changes = reported.into_iter()
+            .filter_map(|field| {
+                let new = unit.state.get(field);
+
+                let old = if unit.known.insert(field) {
+                    None
+                } else {
+                    let old = before.get(field);
+                    if old == new {
+                        return None
+                    }
+                    Some(old)
+                };
+
+                Some(StateChange { address, field, old, new, source: frame.clone() })
+            })
+            .collect();
+
+        Ok(changes)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use packed_struct::PackedStruct;
+
+    use crate::{capture::parse_frames, protocol::{codec::LongFrame, iu::IndoorUnitMode}};
+
+    use super::*;
+
+    #[test]
+    fn test_tracker_capture() {
+        let capture = std::fs::read_to_string("dumps/wrc-main+sub-boot-1IU.txt").unwrap();
+
+        let mut tracker = IndoorUnitTracker::default();
+        let mut changes = vec![];
+
+        for captured in parse_frames(&capture).unwrap() {
+            changes.extend(tracker.observe(&captured.frame).unwrap());
+        }
+
+        // every field is first reported with no previous value
+        // (the capture has no `0x55` polls, so humidity and CO2 are never reported)
+        let first: Vec<_> = changes.iter().filter(|change| change.old.is_none()).map(|change| change.field).collect();
+        let expected: Vec<_> = IndoorUnitField::ALL.into_iter()
+            .filter(|field| !matches!(field, IndoorUnitField::Humidity | IndoorUnitField::Co2))
+            .collect();
+        assert_eq!(first.len(), expected.len());
+        assert!(expected.iter().all(|field| first.contains(field)));
+
+        assert!(changes.iter().all(|change| change.address == 0x20 && Some(change.new) != change.old));
+
+        let state = tracker.state(0x20).unwrap();
+        assert!(state.power);
+        assert_eq!(state.setpoint_temp, 20);
+        assert_eq!(state.room_temp, 22);
+        assert_eq!(state.eva_inlet_temp, 24);
+        assert_eq!(state.eva_outlet_temp, 23);
+        assert_eq!(state.fan_speed, IndoorUnitFanSpeed::Auto);
+        assert_eq!(state.mode, IndoorUnitMode::Heat);
+    }
+
+    #[test]
+    fn test_tracker_unexpected_frames() {
+        let mut tracker = IndoorUnitTracker::default();
+
+        let reply_52 = |data| RxFrame::Long(LongFrame::new(0x20, MAIN_WRC, Command52IndoorUnitResponse::ID, data));
+
+        tracker.observe(&reply_52([0x4b, 0x4c, 0x63, 0xf8, 0x81, 0x10, 0x00, 0x6f])).unwrap();
+        assert_eq!(tracker.state(0x20).unwrap().mode, IndoorUnitMode::Heat);
+
+        // an unknown mode fails to unpack, and leaves the state as it was
+        assert!(tracker.observe(&reply_52([0x4b, 0x4c, 0x63, 0xf8, 0x83, 0x10, 0x00, 0x6f])).is_err());
+        assert_eq!(tracker.state(0x20).unwrap().mode, IndoorUnitMode::Heat);
+
+        // a setpoint below zero doesn't underflow
+        let changes = tracker.observe(&reply_52([0x10, 0x4c, 0x63, 0xf8, 0x81, 0x10, 0x00, 0x6f])).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(tracker.state(0x20).unwrap().setpoint_temp, 0);
+
+        // a settings change ack that doesn't pass `CommandA0::check` is still tracked
+        let mut ack = IndoorUnitState { power: true, setpoint_temp: 24, ..Default::default() }.to_a0().pack().unwrap();
+        ack[4] ^= 0x10;
+        tracker.observe(&RxFrame::Long(LongFrame::new(0x20, MAIN_WRC, 0x50, ack))).unwrap();
+        assert_eq!(tracker.state(0x20).unwrap().setpoint_temp, 24);
+    }
+}
@@ -0,0 +1,129 @@
+//! Regression tests against the captures of real bus traffic in `dumps/`.
+//!
+//! Every frame is run through the codec and, for known commands, unpacked and repacked as its
+//! command struct. The decoded output of each capture is compared against a snapshot in
+//! `tests/snapshots/`, so changes to field layouts or the codec show up as a diff.
+//!
+//! Run with `UPDATE_SNAPSHOTS=1` to (re-)write the snapshots after an intended change.
+
+use std::{fmt::Write, panic::{catch_unwind, AssertUnwindSafe}, path::Path};
+
+use bytes::BytesMut;
+use samsunghvac2mqtt::{capture::{Capture, CapturedFrame}, protocol::{codec::{RxFrame, TxFrame, WrcBusProtocolCodec}, decode::decode, layout::{commands, CommandLayout}}};
+use tokio_util::codec::{Decoder, Encoder};
+
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Encode a frame and decode it again.
+fn check_codec(captured: &CapturedFrame) {
+    let tx = match &captured.frame {
+        RxFrame::Long(frame) => TxFrame::Long(frame.clone()),
+        RxFrame::Short(frame) => TxFrame::Short(frame.clone()),
+        RxFrame::Corrupted(_) => return,
+    };
+
+    let mut codec = WrcBusProtocolCodec::new();
+
+    let mut encoded = BytesMut::new();
+    codec.encode(tx, &mut encoded).unwrap();
+    assert_eq!(&encoded[..], &captured.frame.to_bytes()[..], "encoded {:?}", captured.frame);
+
+    let decoded = codec.decode(&mut encoded).unwrap().expect("a whole frame");
+    assert_eq!(decoded.to_bytes(), captured.frame.to_bytes(), "decoded {:?}", captured.frame);
+    assert!(encoded.is_empty());
+}
+
+/// Unpack and repack a known command. Bits that aren't covered by a field aren't kept.
+fn check_roundtrip(layout: &CommandLayout, data: &[u8]) -> Result<(), String> {
+    let repacked = catch_unwind(AssertUnwindSafe(|| (layout.roundtrip)(data)))
+        .map_err(|_| format!("{} check failed", layout.name))?
+        .map_err(|err| format!("{} failed to unpack: {err:?}", layout.name))?;
+
+    let uncovered = layout.uncovered_bits();
+    let covered = |data: &[u8]| data.iter().zip(&uncovered).map(|(byte, mask)| byte & !mask).collect::<Vec<_>>();
+
+    if covered(&repacked) != covered(data) {
+        return Err(format!("{} repacked as {}", layout.name, hex(&repacked)));
+    }
+
+    Ok(())
+}
+
+fn decode_capture(capture: &Capture, layouts: &[CommandLayout]) -> String {
+    let mut output = String::new();
+
+    for (i, captured) in capture.frames.iter().enumerate() {
+        for note in capture.notes_before(i) {
+            writeln!(output, "# {note}").unwrap();
+        }
+
+        check_codec(captured);
+
+        let (id, data) = match &captured.frame {
+            RxFrame::Long(frame) => (frame.id, &frame.data[..]),
+            RxFrame::Short(frame) => (frame.id, &frame.data[..]),
+            RxFrame::Corrupted(data) => {
+                writeln!(output, "{:8} corrupted {}", captured.elapsed_ms, hex(data)).unwrap();
+                continue;
+            }
+        };
+
+        write!(output, "{:8} {:02x} -> {:02x} {:02x} {:16}", captured.elapsed_ms, id.src, id.dst, id.cmd, hex(data)).unwrap();
+
+        match layouts.iter().find(|layout| layout.matches(id.src, id.dst, id.cmd, data.len())) {
+            Some(layout) => {
+                if let Err(err) = check_roundtrip(layout, data) {
+                    panic!("frame {i} at {} ms ({:02x} -> {:02x} {:02x} {}): {err}", captured.elapsed_ms, id.src, id.dst, id.cmd, hex(data));
+                }
+
+                write!(output, " {}", layout.name).unwrap();
+            },
+            None => write!(output, " ?").unwrap(),
+        }
+
+        for field in decode(&captured.frame).unwrap_or_default() {
+            write!(output, " {}{field}", if field.unknown { "!" } else { "" }).unwrap();
+        }
+
+        writeln!(output).unwrap();
+    }
+
+    output
+}
+
+#[test]
+fn test_captures() {
+    let layouts = commands();
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+
+    let mut paths: Vec<_> = std::fs::read_dir("dumps").unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let mut mismatched = vec![];
+
+    for path in paths {
+        let capture = Capture::parse(&std::fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|err| panic!("{}: {err:?}", path.display()));
+        assert!(!capture.frames.is_empty(), "{}", path.display());
+
+        let decoded = decode_capture(&capture, &layouts);
+
+        let snapshot = Path::new("tests/snapshots").join(path.file_name().unwrap());
+
+        if update {
+            std::fs::create_dir_all("tests/snapshots").unwrap();
+            std::fs::write(&snapshot, &decoded).unwrap();
+        } else if std::fs::read_to_string(&snapshot).ok().as_deref() != Some(decoded.as_str()) {
+            mismatched.push(snapshot.display().to_string());
+        }
+    }
+
+    assert!(mismatched.is_empty(), "decoded output differs from the snapshots (re-run with UPDATE_SNAPSHOTS=1 and review the diff): {mismatched:?}");
+}